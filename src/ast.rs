@@ -1,4 +1,6 @@
+use crate::span::Span;
 use core::fmt;
+use num_bigint::BigInt;
 use std::rc::Rc;
 
 pub type Program = Vec<Statement>;
@@ -10,18 +12,20 @@ pub enum Node {
     // Expression(Expression),
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
-pub enum Statement {
-    LetStatement {
-        name: Identifier,
-        value: Expression,
-    },
-    Return {
-        return_value: Expression,
-    },
-    Expression {
-        expression: Expression,
-    },
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StatementKind {
+    LetStatement { name: Identifier, value: Expression },
+    Return { return_value: Expression },
+    Expression { expression: Expression },
+    // 一番内側のループを抜ける、または次の繰り返しに進む。ループの中にだけ書ける
+    Break,
+    Continue,
     // 使ってないようなのでコメントアウト
     // Block {
     //     statements: Vec<Statement>,
@@ -30,10 +34,12 @@ pub enum Statement {
 
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Statement::LetStatement { name, value } => write!(f, "let {} = {};", name, value),
-            Statement::Return { return_value } => write!(f, "return {};", return_value),
-            Statement::Expression { expression } => write!(f, "{}", expression),
+        match &self.kind {
+            StatementKind::LetStatement { name, value } => write!(f, "let {} = {};", name, value),
+            StatementKind::Return { return_value } => write!(f, "return {};", return_value),
+            StatementKind::Expression { expression } => write!(f, "{}", expression),
+            StatementKind::Break => write!(f, "break;"),
+            StatementKind::Continue => write!(f, "continue;"),
             // 使ってないようなのでコメントアウト
             // Statement::Block { statements } => {
             //     write!(f, "{}", fmt_vec(statements))
@@ -42,11 +48,19 @@ impl fmt::Display for Statement {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
-pub enum Expression {
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExpressionKind {
     Identifier(Identifier),
     Boolean(bool),
     IntegerLiteral(i64),
+    BigIntegerLiteral(BigInt),
+    FloatLiteral(f64),
     PrefixExpression {
         operator: PrefixOperator,
         right: Box<Expression>,
@@ -61,7 +75,20 @@ pub enum Expression {
         consequence: BlockStatement,
         alternative: Option<BlockStatement>,
     },
-        FunctionLiteral {
+    // ループの値はnull
+    While {
+        condition: Box<Expression>,
+        body: BlockStatement,
+    },
+    // 配列の要素、ハッシュのキー、文字列の各文字、rangeの整数を順にvariableに束縛する
+    For {
+        variable: Identifier,
+        iterable: Box<Expression>,
+        body: BlockStatement,
+    },
+    FunctionLiteral {
+        // `let name = fn...`で束縛された関数の名前。エラーのトレースに使う
+        name: Option<Identifier>,
         parameters: Vec<Identifier>,
         body: BlockStatement,
     },
@@ -70,15 +97,29 @@ pub enum Expression {
         arguments: Vec<Expression>,
     },
     StringLiteral(Rc<String>),
+    // `"a${x}b"`。各部分を文字列にしてつなげる
+    TemplateString(Vec<TemplatePart>),
     ArrayLiteral(Vec<Expression>),
     IndexExpression {
         left: Box<Expression>,
         index: Box<Expression>,
     },
     HashLiteral(Vec<HashPair>),
+    // `x = e`と、operatorのある`x += e`などの複合代入。値は代入した値
+    Assign {
+        name: Identifier,
+        operator: Option<InfixOperator>,
+        value: Box<Expression>,
+    },
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplatePart {
+    Literal(Rc<String>),
+    Expression(Expression),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct HashPair {
     pub key: Expression,
     pub value: Expression,
@@ -86,19 +127,21 @@ pub struct HashPair {
 
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expression::Identifier(ident) => write!(f, "{}", ident),
-            Expression::Boolean(b) => write!(f, "{}", b),
-            Expression::IntegerLiteral(i) => write!(f, "{}", i),
-            Expression::PrefixExpression { operator, right } => {
+        match &self.kind {
+            ExpressionKind::Identifier(ident) => write!(f, "{}", ident),
+            ExpressionKind::Boolean(b) => write!(f, "{}", b),
+            ExpressionKind::IntegerLiteral(i) => write!(f, "{}", i),
+            ExpressionKind::BigIntegerLiteral(i) => write!(f, "{}", i),
+            ExpressionKind::FloatLiteral(x) => write!(f, "{:?}", x),
+            ExpressionKind::PrefixExpression { operator, right } => {
                 write!(f, "({}{})", operator, right)
             }
-            Expression::InfixExpression {
+            ExpressionKind::InfixExpression {
                 left,
                 operator,
                 right,
             } => write!(f, "({} {} {})", left, operator, right),
-            Expression::IfExpression {
+            ExpressionKind::IfExpression {
                 condition,
                 consequence,
                 alternative,
@@ -112,10 +155,20 @@ impl fmt::Display for Expression {
                 ),
                 None => write!(f, "if {} {}", condition, fmt_vec(consequence)),
             },
-            Expression::FunctionLiteral { parameters, body } => {
+            ExpressionKind::While { condition, body } => {
+                write!(f, "while {} {}", condition, fmt_vec(body))
+            }
+            ExpressionKind::For {
+                variable,
+                iterable,
+                body,
+            } => write!(f, "for ({} in {}) {}", variable, iterable, fmt_vec(body)),
+            ExpressionKind::FunctionLiteral {
+                parameters, body, ..
+            } => {
                 write!(f, "fn({}) {}", fmt_vec(parameters), fmt_vec(body))
             }
-            Expression::CallExpression {
+            ExpressionKind::CallExpression {
                 function,
                 arguments,
             } => {
@@ -126,8 +179,17 @@ impl fmt::Display for Expression {
                 result = format!("{}({})", function, result);
                 write!(f, "{}", result)
             }
-            Expression::StringLiteral(s) => write!(f, "{}", s),
-            Expression::ArrayLiteral(elements) => {
+            ExpressionKind::StringLiteral(s) => write!(f, "{}", s),
+            ExpressionKind::TemplateString(parts) => {
+                for part in parts {
+                    match part {
+                        TemplatePart::Literal(s) => write!(f, "{}", s)?,
+                        TemplatePart::Expression(e) => write!(f, "${{{}}}", e)?,
+                    }
+                }
+                Ok(())
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
                 let mut result = String::new();
                 for e in elements {
                     result = format!("{} {}", result, e);
@@ -135,10 +197,10 @@ impl fmt::Display for Expression {
                 result = format!("[{}]", result);
                 write!(f, "{}", result)
             }
-            Expression::IndexExpression { left, index } => {
+            ExpressionKind::IndexExpression { left, index } => {
                 write!(f, "({}[{}])", left, index)
             }
-            Expression::HashLiteral(hash) => {
+            ExpressionKind::HashLiteral(hash) => {
                 let mut result = String::new();
                 for map in hash {
                     result = format!("{}{}: {}, ", result, map.key, map.value);
@@ -146,12 +208,20 @@ impl fmt::Display for Expression {
                 result = format!("{{{}}}", result);
                 write!(f, "{}", result)
             }
+            ExpressionKind::Assign {
+                name,
+                operator,
+                value,
+            } => match operator {
+                Some(operator) => write!(f, "({} {}= {})", name, operator, value),
+                None => write!(f, "({} = {})", name, value),
+            },
         }
     }
 }
 
-fn fmt_vec<T: fmt::Display>(vec: &Vec<T>) -> String {
-    vec.into_iter()
+fn fmt_vec<T: fmt::Display>(vec: &[T]) -> String {
+    vec.iter()
         .map(|e| e.to_string())
         .collect::<Vec<_>>()
         .join(", ")
@@ -163,10 +233,23 @@ pub enum InfixOperator {
     Minus,
     Asterisk,
     Slash,
+    Percent,
+    Power,
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Eq,
     NotEq,
+    // 左辺だけで結果が決まるときは右辺を評価しない
+    And,
+    Or,
+    // ビット演算とシフトは整数だけに使える
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
 }
 
 impl fmt::Display for InfixOperator {
@@ -176,10 +259,21 @@ impl fmt::Display for InfixOperator {
             InfixOperator::Minus => write!(f, "-"),
             InfixOperator::Asterisk => write!(f, "*"),
             InfixOperator::Slash => write!(f, "/"),
+            InfixOperator::Percent => write!(f, "%"),
+            InfixOperator::Power => write!(f, "**"),
             InfixOperator::Lt => write!(f, "<"),
             InfixOperator::Gt => write!(f, ">"),
+            InfixOperator::LtEq => write!(f, "<="),
+            InfixOperator::GtEq => write!(f, ">="),
             InfixOperator::Eq => write!(f, "=="),
             InfixOperator::NotEq => write!(f, "!="),
+            InfixOperator::And => write!(f, "&&"),
+            InfixOperator::Or => write!(f, "||"),
+            InfixOperator::BitAnd => write!(f, "&"),
+            InfixOperator::BitOr => write!(f, "|"),
+            InfixOperator::BitXor => write!(f, "^"),
+            InfixOperator::ShiftLeft => write!(f, "<<"),
+            InfixOperator::ShiftRight => write!(f, ">>"),
         }
     }
 }
@@ -188,6 +282,7 @@ impl fmt::Display for InfixOperator {
 pub enum PrefixOperator {
     Bang,
     Minus,
+    BitNot,
 }
 
 impl fmt::Display for PrefixOperator {
@@ -195,6 +290,7 @@ impl fmt::Display for PrefixOperator {
         match self {
             PrefixOperator::Bang => write!(f, "!"),
             PrefixOperator::Minus => write!(f, "-"),
+            PrefixOperator::BitNot => write!(f, "~"),
         }
     }
 }
@@ -202,11 +298,19 @@ impl fmt::Display for PrefixOperator {
 #[derive(Debug, Eq, PartialEq, PartialOrd, Hash, Clone)]
 pub enum Precedence {
     Lowest,
+    Assign,      // = or +=
+    LogicalOr,   // ||
+    LogicalAnd,  // &&
+    BitOr,       // |
+    BitXor,      // ^
+    BitAnd,      // &
     Equals,      // ==
     LessGreater, // > or <
+    Shift,       // << or >>
     Sum,         // +
     Product,     // *
     Prefix,      // -X or !X
+    Power,       // **
     Call,        // myFunction(X)
     Index,       // array[index]
 }
@@ -0,0 +1,219 @@
+use crate::lexer::Lexer;
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::repl::{self, ReplMode};
+use crate::runtime::{Backend, Runtime};
+use crate::span::Files;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::rc::Rc;
+mod test;
+
+const USAGE: &str = "\
+Usage: rust-monkey [options] [--] [file [args...]]
+
+Runs a Monkey script. Without a file, reads the program from stdin when it is
+piped, and starts the REPL otherwise. Arguments after `--` are not treated as
+options, and a `--` right after the file or -e <code> is dropped.
+
+Options:
+  -e <code>         Evaluate <code> and print its value
+  --mode <mode>     eval (default), parser or lexer
+  --backend <name>  evaluator (default) or vm
+  -h, --help        Show this help";
+
+// 終了コード
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    File(String),
+    Inline(String),
+    Stdin,
+}
+
+impl Source {
+    // `-`は標準入力、それ以外はファイル名
+    fn from_arg(arg: &str) -> Source {
+        match arg {
+            "-" => Source::Stdin,
+            file => Source::File(file.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub mode: ReplMode,
+    pub backend: Backend,
+    pub source: Option<Source>,
+    pub args: Vec<String>,
+    pub help: bool,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        mode: ReplMode::Eval,
+        backend: Backend::Evaluator,
+        source: None,
+        args: Vec::new(),
+        help: false,
+    };
+    let mut iter = args.iter();
+    let mut separated = false;
+    while let Some(arg) = iter.next() {
+        // ファイル名かインラインのコードが決まったら、残りはスクリプトの引数
+        if options.source.is_some() {
+            // `script.mk -- --verbose`のように、直後の`--`は区切りとして読み捨てる
+            if arg == "--" && !separated && options.args.is_empty() {
+                separated = true;
+            } else {
+                options.args.push(arg.clone());
+            }
+            continue;
+        }
+        match arg.as_str() {
+            // `--`より後はオプションとして解釈しない
+            "--" => {
+                separated = true;
+                if let Some(file) = iter.next() {
+                    options.source = Some(Source::from_arg(file));
+                }
+            }
+            "-e" => match iter.next() {
+                Some(code) => options.source = Some(Source::Inline(code.clone())),
+                None => return Err("-e requires an argument".to_string()),
+            },
+            "--mode" => match iter.next() {
+                Some(mode) => options.mode = mode.parse()?,
+                None => return Err("--mode requires an argument".to_string()),
+            },
+            "--backend" => match iter.next() {
+                Some(backend) => options.backend = backend.parse()?,
+                None => return Err("--backend requires an argument".to_string()),
+            },
+            "-h" | "--help" => options.help = true,
+            "-" => options.source = Some(Source::Stdin),
+            option if option.starts_with('-') => {
+                return Err(format!("unknown option: {}", option));
+            }
+            file => options.source = Some(Source::File(file.to_string())),
+        }
+    }
+    Ok(options)
+}
+
+// コマンドライン引数を解釈して実行し、終了コードを返す
+pub fn run(args: &[String]) -> i32 {
+    let mut options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rust-monkey: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    let source = match options.source.take() {
+        Some(source) => source,
+        None if io::stdin().is_terminal() => {
+            repl::start(options.mode, options.backend);
+            return 0;
+        }
+        None => Source::Stdin,
+    };
+
+    if options.mode == ReplMode::Lexre {
+        return stream_tokens(&source);
+    }
+
+    let (name, input) = match read_source(&source) {
+        Ok(read) => read,
+        Err(message) => {
+            eprintln!("rust-monkey: {}", message);
+            return EXIT_USAGE;
+        }
+    };
+    let print_result = matches!(source, Source::Inline(_));
+    run_source(&name, &input, &options, print_result)
+}
+
+// 字句解析の結果だけなら、入力を読みながら順に表示する
+fn stream_tokens(source: &Source) -> i32 {
+    let lexer = match source {
+        Source::File(path) => match fs::File::open(path) {
+            Ok(file) => Lexer::from_reader(file),
+            Err(e) => {
+                eprintln!("rust-monkey: cannot read {}: {}", path, e);
+                return EXIT_USAGE;
+            }
+        },
+        Source::Inline(code) => Lexer::new(code),
+        Source::Stdin => Lexer::from_reader(io::stdin()),
+    };
+    repl::print_tokens(lexer);
+    0
+}
+
+fn read_source(source: &Source) -> Result<(String, String), String> {
+    match source {
+        Source::File(path) => fs::read_to_string(path)
+            .map(|input| (path.clone(), input))
+            .map_err(|e| format!("cannot read {}: {}", path, e)),
+        Source::Inline(code) => Ok(("-e".to_string(), code.clone())),
+        Source::Stdin => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|e| format!("cannot read stdin: {}", e))?;
+            Ok(("<stdin>".to_string(), input))
+        }
+    }
+}
+
+fn run_source(name: &str, input: &str, options: &Options, print_result: bool) -> i32 {
+    let mode = options.mode;
+    let mut files = Files::default();
+    let file = files.add(name);
+    let mut parser = Parser::new(Lexer::new(input).with_file(file));
+    let (program, errors) = parser.program();
+    if mode == ReplMode::Parser {
+        for statement in &program {
+            println!("{}", statement);
+        }
+    }
+    if !errors.is_empty() {
+        for error in errors {
+            eprintln!("{}", error.render(&files));
+        }
+        return EXIT_FAILURE;
+    }
+    if mode == ReplMode::Parser {
+        return 0;
+    }
+
+    let mut runtime = Runtime::new(options.backend);
+    let args = options
+        .args
+        .iter()
+        .map(|a| Rc::new(Object::String(Rc::new(a.clone()))))
+        .collect();
+    runtime.set("args", Rc::new(Object::Array(args)));
+
+    match runtime.run(program) {
+        Ok(value) => {
+            if print_result && *value != Object::Null {
+                println!("{}", value);
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("{}", error.render(&files));
+            EXIT_FAILURE
+        }
+    }
+}
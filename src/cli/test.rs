@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use crate::cli::{parse_args, Options, Source};
+    use crate::repl::ReplMode;
+    use crate::runtime::Backend;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args(&[])),
+            Ok(Options {
+                mode: ReplMode::Eval,
+                backend: Backend::Evaluator,
+                source: None,
+                args: vec![],
+                help: false,
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&["script.mk", "a", "-e", "b"])),
+            Ok(Options {
+                mode: ReplMode::Eval,
+                backend: Backend::Evaluator,
+                source: Some(Source::File("script.mk".to_string())),
+                args: args(&["a", "-e", "b"]),
+                help: false,
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&["--mode", "parser", "-e", "1 + 2", "x"])),
+            Ok(Options {
+                mode: ReplMode::Parser,
+                backend: Backend::Evaluator,
+                source: Some(Source::Inline("1 + 2".to_string())),
+                args: args(&["x"]),
+                help: false,
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&["--mode", "lexer", "-"])),
+            Ok(Options {
+                mode: ReplMode::Lexre,
+                backend: Backend::Evaluator,
+                source: Some(Source::Stdin),
+                args: vec![],
+                help: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        let options = parse_args(&args(&["--backend", "vm", "-e", "1"])).unwrap();
+        assert_eq!(options.backend, Backend::Vm);
+        assert_eq!(
+            parse_args(&args(&["--backend", "jit"])),
+            Err("unknown backend: jit (expected evaluator or vm)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_help_after_script_is_an_argument() {
+        let options = parse_args(&args(&["-h"])).unwrap();
+        assert!(options.help);
+        let options = parse_args(&args(&["script.mk", "-h"])).unwrap();
+        assert!(!options.help);
+        assert_eq!(options.args, args(&["-h"]));
+    }
+
+    #[test]
+    fn test_double_dash_separator() {
+        let options = parse_args(&args(&["--", "-script.mk", "--mode"])).unwrap();
+        assert_eq!(options.source, Some(Source::File("-script.mk".to_string())));
+        assert_eq!(options.mode, ReplMode::Eval);
+        assert_eq!(options.args, args(&["--mode"]));
+
+        let options = parse_args(&args(&["script.mk", "--", "--help", "--"])).unwrap();
+        assert!(!options.help);
+        assert_eq!(options.args, args(&["--help", "--"]));
+
+        let options = parse_args(&args(&["-e", "args", "--", "-x"])).unwrap();
+        assert_eq!(options.source, Some(Source::Inline("args".to_string())));
+        assert_eq!(options.args, args(&["-x"]));
+
+        let options = parse_args(&args(&["--", "-", "a"])).unwrap();
+        assert_eq!(options.source, Some(Source::Stdin));
+        assert_eq!(options.args, args(&["a"]));
+
+        let options = parse_args(&args(&["--mode", "parser", "--"])).unwrap();
+        assert_eq!(options.source, None);
+        assert_eq!(options.mode, ReplMode::Parser);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(
+            parse_args(&args(&["-e"])),
+            Err("-e requires an argument".to_string())
+        );
+        assert_eq!(
+            parse_args(&args(&["--mode", "vm"])),
+            Err("unknown mode: vm (expected eval, parser or lexer)".to_string())
+        );
+        assert_eq!(
+            parse_args(&args(&["--verbose"])),
+            Err("unknown option: --verbose".to_string())
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::ast::Identifier;
+use crate::span::Span;
+use std::fmt;
+mod test;
+
+// 命令は1バイトのオペコードと、それに続くビッグエンディアンのオペランドからなる
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Null,
+    True,
+    False,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessEqual,
+    GreaterEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Minus,
+    Bang,
+    BitNot,
+    Jump,
+    JumpNotTruthy,
+    JumpIfDefined,
+    EnterLoop,
+    ExitLoop,
+    Unwind,
+    Iter,
+    IterNext,
+    GetGlobal,
+    SetGlobal,
+    AssignGlobal,
+    GetLocal,
+    SetLocal,
+    GetFree,
+    DefineCell,
+    GetCell,
+    SetCell,
+    CannotAssign,
+    GetBuiltin,
+    Array,
+    Hash,
+    Concat,
+    Index,
+    Call,
+    ReturnValue,
+    Closure,
+}
+
+// バイトからオペコードに戻すための表。宣言順に並べる
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Null,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Mod,
+    Opcode::Pow,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::LessThan,
+    Opcode::GreaterThan,
+    Opcode::LessEqual,
+    Opcode::GreaterEqual,
+    Opcode::BitAnd,
+    Opcode::BitOr,
+    Opcode::BitXor,
+    Opcode::ShiftLeft,
+    Opcode::ShiftRight,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::BitNot,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::JumpIfDefined,
+    Opcode::EnterLoop,
+    Opcode::ExitLoop,
+    Opcode::Unwind,
+    Opcode::Iter,
+    Opcode::IterNext,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::AssignGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::DefineCell,
+    Opcode::GetCell,
+    Opcode::SetCell,
+    Opcode::CannotAssign,
+    Opcode::GetBuiltin,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Concat,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Opcode {
+        OPCODES[byte as usize]
+    }
+
+    // 各オペランドのバイト数
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::JumpIfDefined
+            | Opcode::IterNext
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::AssignGlobal
+            | Opcode::CannotAssign
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::Concat => &[4],
+            Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::DefineCell
+            | Opcode::GetFree
+            | Opcode::Call => &[2],
+            Opcode::GetBuiltin => &[1],
+            // 関数の定数番号と、捕捉する自由変数の数
+            Opcode::Closure => &[4, 2],
+            _ => &[],
+        }
+    }
+}
+
+pub fn make(op: Opcode, operands: &[usize]) -> Vec<u8> {
+    let widths = op.operand_widths();
+    assert_eq!(widths.len(), operands.len(), "wrong operands for {:?}", op);
+    let mut instruction = vec![op as u8];
+    for (&operand, &width) in operands.iter().zip(widths) {
+        assert!(
+            operand >> (width * 8) == 0,
+            "operand {} of {:?} does not fit in {} bytes",
+            operand,
+            op,
+            width
+        );
+        let bytes = (operand as u64).to_be_bytes();
+        instruction.extend_from_slice(&bytes[8 - width..]);
+    }
+    instruction
+}
+
+pub fn read_operand(instructions: &[u8], offset: usize, width: usize) -> usize {
+    instructions[offset..offset + width]
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as usize)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Instructions(pub Vec<u8>);
+
+// 逆アセンブルした命令を1行ずつ`0000 Constant 1`の形式で出力する
+impl fmt::Display for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.0.len() {
+            let op = Opcode::from_byte(self.0[offset]);
+            write!(f, "{:04} {:?}", offset, op)?;
+            offset += 1;
+            for &width in op.operand_widths() {
+                write!(f, " {}", read_operand(&self.0, offset, width))?;
+                offset += width;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledFunction {
+    pub name: Option<Identifier>,
+    pub instructions: Instructions,
+    // 引数を含むローカル変数の数
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    // 命令の位置と、その命令を生成した式のspan。位置の昇順に並ぶ。
+    // Hashだけは各キーのspanを同じ位置に順に並べる
+    pub positions: Vec<(usize, Span)>,
+}
+
+impl CompiledFunction {
+    // offsetの命令に対応する最初のspan
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        self.spans_at(offset).next()
+    }
+
+    pub fn spans_at(&self, offset: usize) -> impl Iterator<Item = Span> + '_ {
+        let start = self.positions.partition_point(|&(o, _)| o < offset);
+        self.positions[start..]
+            .iter()
+            .take_while(move |&&(o, _)| o == offset)
+            .map(|&(_, span)| span)
+    }
+}
@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use crate::code::{make, read_operand, Instructions, Opcode};
+
+    #[test]
+    fn test_make() {
+        assert_eq!(
+            make(Opcode::Constant, &[65534]),
+            vec![Opcode::Constant as u8, 0, 0, 255, 254]
+        );
+        assert_eq!(
+            make(Opcode::GetLocal, &[255]),
+            vec![Opcode::GetLocal as u8, 0, 255]
+        );
+        assert_eq!(
+            make(Opcode::Closure, &[65534, 255]),
+            vec![Opcode::Closure as u8, 0, 0, 255, 254, 0, 255]
+        );
+        assert_eq!(make(Opcode::Add, &[]), vec![Opcode::Add as u8]);
+    }
+
+    #[test]
+    fn test_read_operand() {
+        let instruction = make(Opcode::Closure, &[70000, 300]);
+        assert_eq!(read_operand(&instruction, 1, 4), 70000);
+        assert_eq!(read_operand(&instruction, 5, 2), 300);
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for byte in 0..=Opcode::Closure as u8 {
+            assert_eq!(Opcode::from_byte(byte) as u8, byte);
+        }
+    }
+
+    #[test]
+    fn test_instructions_display() {
+        let instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Closure, &[65535, 255]),
+        ]
+        .concat();
+        assert_eq!(
+            Instructions(instructions).to_string(),
+            "0000 Add\n0001 GetLocal 1\n0004 Constant 2\n0009 Closure 65535 255\n"
+        );
+    }
+}
@@ -0,0 +1,674 @@
+use crate::ast::{self, Identifier};
+use crate::code::{make, CompiledFunction, Instructions, Opcode};
+use crate::object::{builtins, Object};
+use crate::span::Span;
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+pub mod symbol_table;
+mod test;
+
+use symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+// 関数1つ分の出力
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Vec<u8>,
+    positions: Vec<(usize, Span)>,
+    // コンパイル中のループ。内側のループが末尾に来る
+    loops: Vec<LoopContext>,
+    // コンパイル中のブロックの深さ。1なら関数の本体の一番外側
+    block_depth: usize,
+}
+
+// continueの飛び先と、ループの終わりを指すよう後で書き換えるbreakのジャンプ
+struct LoopContext {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+// ast::Programをバイトコードに変換する。
+// 定数表とグローバル変数のシンボル表はコンパイルをまたいで引き継ぐので、
+// REPLでは1つのCompilerを使い続ける
+pub struct Compiler {
+    constants: Vec<Rc<Object>>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            constants: Vec::new(),
+            symbol_table: SymbolTable::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn constants(&self) -> &[Rc<Object>] {
+        &self.constants
+    }
+
+    pub fn global_names(&self) -> Vec<Identifier> {
+        self.symbol_table.global_names()
+    }
+
+    pub fn define_global(&mut self, name: &str) -> usize {
+        self.symbol_table.define(&Rc::new(name.to_string())).index
+    }
+
+    // プログラムを最後の文の値を返す関数としてコンパイルする
+    pub fn compile(&mut self, program: &ast::Program) -> Rc<CompiledFunction> {
+        self.scopes.push(CompilationScope::default());
+        self.compile_block(program);
+        self.emit(Opcode::ReturnValue, &[]);
+        let scope = self.scopes.pop().unwrap();
+        Rc::new(CompiledFunction {
+            name: None,
+            instructions: Instructions(scope.instructions),
+            num_locals: 0,
+            num_parameters: 0,
+            positions: scope.positions,
+        })
+    }
+
+    // ブロックは必ず値を1つだけスタックに残す
+    fn compile_block(&mut self, block: &[ast::Statement]) {
+        if block.is_empty() {
+            self.emit(Opcode::Null, &[]);
+            return;
+        }
+        self.scope().block_depth += 1;
+        for (i, statement) in block.iter().enumerate() {
+            if i != 0 {
+                self.emit(Opcode::Pop, &[]);
+            }
+            self.compile_statement(statement);
+        }
+        self.scope().block_depth -= 1;
+    }
+
+    fn compile_statement(&mut self, statement: &ast::Statement) {
+        match &statement.kind {
+            ast::StatementKind::LetStatement { name, value } => {
+                self.compile_expression(value);
+                self.define_variable(name);
+                // 条件分岐やループの中でなければ、この後は必ず値が入っている
+                if self.scope().block_depth == 1 {
+                    self.symbol_table.unset.remove(name);
+                }
+                // let文の値はnull
+                self.emit(Opcode::Null, &[]);
+            }
+            ast::StatementKind::Return { return_value } => {
+                self.compile_expression(return_value);
+                self.emit(Opcode::ReturnValue, &[]);
+            }
+            ast::StatementKind::Expression { expression } => self.compile_expression(expression),
+            // 飛んだ先に進むので、文の値は残さない
+            ast::StatementKind::Break => {
+                self.emit(Opcode::Unwind, &[]);
+                let jump = self.emit(Opcode::Jump, &[0]);
+                self.scope().loops.last_mut().unwrap().breaks.push(jump);
+            }
+            ast::StatementKind::Continue => {
+                self.emit(Opcode::Unwind, &[]);
+                let start = self.scope().loops.last().unwrap().start;
+                self.emit(Opcode::Jump, &[start]);
+            }
+        }
+    }
+
+    fn compile_expression(&mut self, expression: &ast::Expression) {
+        let span = expression.span;
+        match &expression.kind {
+            ast::ExpressionKind::Identifier(name) => {
+                let symbol = self.resolve(name);
+                self.load_value(&symbol, span);
+            }
+            ast::ExpressionKind::Boolean(true) => {
+                self.emit(Opcode::True, &[]);
+            }
+            ast::ExpressionKind::Boolean(false) => {
+                self.emit(Opcode::False, &[]);
+            }
+            ast::ExpressionKind::IntegerLiteral(i) => {
+                let index = self.add_constant(Object::Integer(*i));
+                self.emit(Opcode::Constant, &[index]);
+            }
+            ast::ExpressionKind::BigIntegerLiteral(i) => {
+                let index = self.add_constant(Object::BigInteger(i.clone()));
+                self.emit(Opcode::Constant, &[index]);
+            }
+            ast::ExpressionKind::FloatLiteral(x) => {
+                let index = self.add_constant(Object::Float(*x));
+                self.emit(Opcode::Constant, &[index]);
+            }
+            ast::ExpressionKind::StringLiteral(s) => {
+                let index = self.add_constant(Object::String(s.clone()));
+                self.emit(Opcode::Constant, &[index]);
+            }
+            ast::ExpressionKind::TemplateString(parts) => {
+                for part in parts {
+                    match part {
+                        ast::TemplatePart::Literal(s) => {
+                            let index = self.add_constant(Object::String(s.clone()));
+                            self.emit(Opcode::Constant, &[index]);
+                        }
+                        ast::TemplatePart::Expression(e) => self.compile_expression(e),
+                    }
+                }
+                self.emit(Opcode::Concat, &[parts.len()]);
+            }
+            ast::ExpressionKind::PrefixExpression { operator, right } => {
+                self.compile_expression(right);
+                let op = match operator {
+                    ast::PrefixOperator::Bang => Opcode::Bang,
+                    ast::PrefixOperator::Minus => Opcode::Minus,
+                    ast::PrefixOperator::BitNot => Opcode::BitNot,
+                };
+                let position = self.emit(op, &[]);
+                self.mark(position, span);
+            }
+            ast::ExpressionKind::InfixExpression {
+                left,
+                operator: operator @ (ast::InfixOperator::And | ast::InfixOperator::Or),
+                right,
+            } => self.compile_logical(operator, left, right),
+            ast::ExpressionKind::InfixExpression {
+                left,
+                operator,
+                right,
+            } => {
+                self.compile_expression(left);
+                self.compile_expression(right);
+                let position = self.emit(infix_opcode(operator), &[]);
+                self.mark(position, span);
+            }
+            ast::ExpressionKind::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(condition);
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0]);
+                self.compile_block(consequence);
+                let jump = self.emit(Opcode::Jump, &[0]);
+                self.patch_jump(jump_not_truthy);
+                match alternative {
+                    Some(alternative) => self.compile_block(alternative),
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+                self.patch_jump(jump);
+            }
+            // ループの値はnull
+            ast::ExpressionKind::While { condition, body } => {
+                self.emit(Opcode::EnterLoop, &[]);
+                let start = self.scope().instructions.len();
+                self.compile_expression(condition);
+                let exit = self.emit(Opcode::JumpNotTruthy, &[0]);
+                self.compile_loop_body(start, exit, body);
+                self.emit(Opcode::Null, &[]);
+            }
+            // 繰り返す値の並びと次の位置は、ループの間スタックに置いておく
+            ast::ExpressionKind::For {
+                variable,
+                iterable,
+                body,
+            } => {
+                self.compile_expression(iterable);
+                let position = self.emit(Opcode::Iter, &[]);
+                self.mark(position, iterable.span);
+                self.emit(Opcode::EnterLoop, &[]);
+                let start = self.scope().instructions.len();
+                let exit = self.emit(Opcode::IterNext, &[0]);
+                self.define_variable(variable);
+                self.compile_loop_body(start, exit, body);
+                self.emit(Opcode::Pop, &[]);
+                self.emit(Opcode::Pop, &[]);
+                self.emit(Opcode::Null, &[]);
+            }
+            ast::ExpressionKind::FunctionLiteral {
+                name,
+                parameters,
+                body,
+            } => self.compile_function(name, parameters, body),
+            ast::ExpressionKind::CallExpression {
+                function,
+                arguments,
+            } => {
+                self.compile_expression(function);
+                for argument in arguments {
+                    self.compile_expression(argument);
+                }
+                let position = self.emit(Opcode::Call, &[arguments.len()]);
+                self.mark(position, span);
+            }
+            ast::ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.compile_expression(element);
+                }
+                self.emit(Opcode::Array, &[elements.len()]);
+            }
+            ast::ExpressionKind::IndexExpression { left, index } => {
+                self.compile_expression(left);
+                self.compile_expression(index);
+                let position = self.emit(Opcode::Index, &[]);
+                self.mark(position, span);
+            }
+            ast::ExpressionKind::HashLiteral(pairs) => {
+                for pair in pairs {
+                    self.compile_expression(&pair.key);
+                    self.compile_expression(&pair.value);
+                }
+                // キーが使えないときのエラーは、評価器と同じくそのキーの位置で報告する
+                let position = self.emit(Opcode::Hash, &[pairs.len()]);
+                for pair in pairs {
+                    self.mark(position, pair.key.span);
+                }
+            }
+            ast::ExpressionKind::Assign {
+                name,
+                operator,
+                value,
+            } => {
+                let symbol = self.resolve(name);
+                match operator {
+                    Some(operator) => {
+                        self.load_value(&symbol, span);
+                        self.compile_expression(value);
+                        let position = self.emit(infix_opcode(operator), &[]);
+                        self.mark(position, span);
+                    }
+                    None => self.compile_expression(value),
+                }
+                self.store_symbol(&symbol, span);
+                self.load_value(&symbol, span);
+            }
+        }
+    }
+
+    // 本体を実行してstartに戻る。exitのジャンプとbreakはループを出る命令に飛ばす
+    fn compile_loop_body(&mut self, start: usize, exit: usize, body: &ast::BlockStatement) {
+        self.scope().loops.push(LoopContext {
+            start,
+            breaks: Vec::new(),
+        });
+        self.compile_block(body);
+        self.emit(Opcode::Pop, &[]);
+        self.emit(Opcode::Jump, &[start]);
+        let context = self.scope().loops.pop().unwrap();
+        self.patch_jump(exit);
+        for jump in context.breaks {
+            self.patch_jump(jump);
+        }
+        self.emit(Opcode::ExitLoop, &[]);
+    }
+
+    // `&&`と`||`はジャンプで右辺を飛ばし、結果を真偽値にそろえる
+    fn compile_logical(
+        &mut self,
+        operator: &ast::InfixOperator,
+        left: &ast::Expression,
+        right: &ast::Expression,
+    ) {
+        self.compile_expression(left);
+        let skip_right = self.emit(Opcode::JumpNotTruthy, &[0]);
+        let mut jumps_to_end = Vec::new();
+        if *operator == ast::InfixOperator::Or {
+            self.emit(Opcode::True, &[]);
+            jumps_to_end.push(self.emit(Opcode::Jump, &[0]));
+            self.patch_jump(skip_right);
+        }
+        self.compile_expression(right);
+        let right_false = self.emit(Opcode::JumpNotTruthy, &[0]);
+        self.emit(Opcode::True, &[]);
+        jumps_to_end.push(self.emit(Opcode::Jump, &[0]));
+        if *operator == ast::InfixOperator::And {
+            self.patch_jump(skip_right);
+        }
+        self.patch_jump(right_false);
+        self.emit(Opcode::False, &[]);
+        for jump in jumps_to_end {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn compile_function(
+        &mut self,
+        name: &Option<Identifier>,
+        parameters: &[Identifier],
+        body: &ast::BlockStatement,
+    ) {
+        self.scopes.push(CompilationScope::default());
+        let outer = mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+        let mut bindings = Bindings::default();
+        scan_block(body, false, &mut bindings);
+        self.symbol_table.cells = bindings.captured;
+        // 内側の関数から参照される引数は、受け取った値をセルに入れ直す
+        for parameter in parameters {
+            let symbol = self.symbol_table.define(parameter);
+            if self.symbol_table.is_cell(&symbol) {
+                self.emit(Opcode::GetLocal, &[symbol.index]);
+                self.emit(Opcode::DefineCell, &[symbol.index]);
+            }
+        }
+        // 後でletする名前を先に参照するクロージャもあるので、セルは最初に作っておく。
+        // letを実行するまでは、値がまだないことを表すUnsetを入れておく
+        for name in &bindings.declared {
+            if let Some(symbol) = self.symbol_table.declare(name) {
+                self.symbol_table.unset.insert(name.clone());
+                if self.symbol_table.is_cell(&symbol) {
+                    self.emit(Opcode::GetLocal, &[symbol.index]);
+                    self.emit(Opcode::DefineCell, &[symbol.index]);
+                }
+            }
+        }
+        self.compile_block(body);
+        self.emit(Opcode::ReturnValue, &[]);
+
+        let scope = self.scopes.pop().unwrap();
+        let inner = mem::take(&mut self.symbol_table);
+        self.symbol_table = *inner.outer.unwrap();
+        for free in &inner.free_symbols {
+            self.load_symbol(free);
+        }
+        let function = CompiledFunction {
+            name: name.clone(),
+            instructions: Instructions(scope.instructions),
+            num_locals: inner.num_definitions,
+            num_parameters: parameters.len(),
+            positions: scope.positions,
+        };
+        let index = self.add_constant(Object::CompiledFunction(Rc::new(function)));
+        self.emit(Opcode::Closure, &[index, inner.free_symbols.len()]);
+    }
+
+    fn resolve(&mut self, name: &Identifier) -> Symbol {
+        match self.symbol_table.resolve(name) {
+            Some(symbol) => symbol,
+            None => self.resolve_global(name),
+        }
+    }
+
+    // letする前のローカル変数の代わりに使う、外側のスコープの変数
+    fn resolve_fallback(&mut self, symbol: &Symbol) -> Symbol {
+        match self.symbol_table.fallback(symbol) {
+            Some(fallback) => fallback,
+            None => self.resolve_global(&symbol.name),
+        }
+    }
+
+    // 見つからない名前は組込み関数か、後で定義されるグローバル変数とみなす。
+    // 最後まで定義されなければ実行時にエラーになる
+    fn resolve_global(&mut self, name: &Identifier) -> Symbol {
+        if let Some(index) = builtins::BUILTINS.iter().position(|b| b.name == **name) {
+            return Symbol {
+                name: name.clone(),
+                scope: SymbolScope::Builtin,
+                index,
+            };
+        }
+        self.symbol_table.define_global(name)
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) -> usize {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index]),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+        }
+    }
+
+    // 変数の値を積み、spanをエラーの位置にする。セルに入っている変数は中身を取り出す。
+    // letする前かもしれない変数は、値がなければ外側のスコープの変数を読む
+    fn load_value(&mut self, symbol: &Symbol, span: Span) {
+        let position = self.load_symbol(symbol);
+        self.mark(position, span);
+        if self.symbol_table.is_cell(symbol) {
+            self.emit(Opcode::GetCell, &[]);
+        }
+        if self.symbol_table.may_be_unset(symbol) {
+            let defined = self.emit(Opcode::JumpIfDefined, &[0]);
+            let fallback = self.resolve_fallback(symbol);
+            self.load_value(&fallback, span);
+            self.patch_jump(defined);
+        }
+    }
+
+    // 変数を定義し、スタックの先頭の値を入れる
+    fn define_variable(&mut self, name: &Identifier) -> Symbol {
+        let symbol = self.symbol_table.define(name);
+        let op = if self.symbol_table.is_cell(&symbol) {
+            Opcode::DefineCell
+        } else {
+            match symbol.scope {
+                SymbolScope::Global => Opcode::SetGlobal,
+                _ => Opcode::SetLocal,
+            }
+        };
+        self.emit(op, &[symbol.index]);
+        symbol
+    }
+
+    // スタックの先頭の値を変数に代入する。
+    // letする前かもしれない変数は、値がなければ外側のスコープの変数に代入する
+    fn store_symbol(&mut self, symbol: &Symbol, span: Span) {
+        if !self.symbol_table.may_be_unset(symbol) {
+            self.store_defined(symbol, span);
+            return;
+        }
+        self.load_symbol(symbol);
+        if self.symbol_table.is_cell(symbol) {
+            self.emit(Opcode::GetCell, &[]);
+        }
+        let defined = self.emit(Opcode::JumpIfDefined, &[0]);
+        let fallback = self.resolve_fallback(symbol);
+        self.store_symbol(&fallback, span);
+        let end = self.emit(Opcode::Jump, &[0]);
+        self.patch_jump(defined);
+        self.emit(Opcode::Pop, &[]);
+        self.store_defined(symbol, span);
+        self.patch_jump(end);
+    }
+
+    fn store_defined(&mut self, symbol: &Symbol, span: Span) {
+        if self.symbol_table.is_cell(symbol) {
+            self.load_symbol(symbol);
+            self.emit(Opcode::SetCell, &[]);
+            return;
+        }
+        let position = match symbol.scope {
+            // 定義されていなければ実行時にエラーになる
+            SymbolScope::Global => self.emit(Opcode::AssignGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::SetLocal, &[symbol.index]),
+            // 組込み関数は変数ではないので代入できない
+            _ => {
+                let index = self.add_constant(Object::String(symbol.name.clone()));
+                self.emit(Opcode::CannotAssign, &[index])
+            }
+        };
+        self.mark(position, span);
+    }
+
+    fn add_constant(&mut self, object: Object) -> usize {
+        self.constants.push(Rc::new(object));
+        self.constants.len() - 1
+    }
+
+    fn scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    // 命令を追加し、その位置を返す
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let instructions = &mut self.scope().instructions;
+        let position = instructions.len();
+        instructions.extend(make(op, operands));
+        position
+    }
+
+    // 実行時エラーになりうる命令に、エラーの位置としてspanを対応付ける
+    fn mark(&mut self, position: usize, span: Span) {
+        self.scope().positions.push((position, span));
+    }
+
+    // positionのジャンプ命令の飛び先を現在の位置にする
+    fn patch_jump(&mut self, position: usize) {
+        let instructions = &mut self.scope().instructions;
+        let op = Opcode::from_byte(instructions[position]);
+        let target = instructions.len();
+        let patched = make(op, &[target]);
+        instructions[position..position + patched.len()].copy_from_slice(&patched);
+    }
+}
+
+fn infix_opcode(operator: &ast::InfixOperator) -> Opcode {
+    match operator {
+        ast::InfixOperator::Plus => Opcode::Add,
+        ast::InfixOperator::Minus => Opcode::Sub,
+        ast::InfixOperator::Asterisk => Opcode::Mul,
+        ast::InfixOperator::Slash => Opcode::Div,
+        ast::InfixOperator::Percent => Opcode::Mod,
+        ast::InfixOperator::Power => Opcode::Pow,
+        ast::InfixOperator::Lt => Opcode::LessThan,
+        ast::InfixOperator::Gt => Opcode::GreaterThan,
+        ast::InfixOperator::LtEq => Opcode::LessEqual,
+        ast::InfixOperator::GtEq => Opcode::GreaterEqual,
+        ast::InfixOperator::Eq => Opcode::Equal,
+        ast::InfixOperator::NotEq => Opcode::NotEqual,
+        ast::InfixOperator::BitAnd => Opcode::BitAnd,
+        ast::InfixOperator::BitOr => Opcode::BitOr,
+        ast::InfixOperator::BitXor => Opcode::BitXor,
+        ast::InfixOperator::ShiftLeft => Opcode::ShiftLeft,
+        ast::InfixOperator::ShiftRight => Opcode::ShiftRight,
+        ast::InfixOperator::And | ast::InfixOperator::Or => unreachable!(),
+    }
+}
+
+// 関数の本体で定義する名前と、内側の関数から参照される名前
+#[derive(Default)]
+struct Bindings {
+    // letやforで定義する名前。内側の関数の中は含まず、現れた順に並べる
+    declared: Vec<Identifier>,
+    captured: HashSet<Identifier>,
+}
+
+impl Bindings {
+    fn declare(&mut self, name: &Identifier) {
+        if !self.declared.contains(name) {
+            self.declared.push(name.clone());
+        }
+    }
+}
+
+// nestedは内側の関数の中を調べているかどうか
+fn scan_block(block: &[ast::Statement], nested: bool, bindings: &mut Bindings) {
+    for statement in block {
+        match &statement.kind {
+            ast::StatementKind::LetStatement { name, value } => {
+                if !nested {
+                    bindings.declare(name);
+                }
+                scan_expression(value, nested, bindings);
+            }
+            ast::StatementKind::Return { return_value } => {
+                scan_expression(return_value, nested, bindings)
+            }
+            ast::StatementKind::Expression { expression } => {
+                scan_expression(expression, nested, bindings)
+            }
+            ast::StatementKind::Break | ast::StatementKind::Continue => (),
+        }
+    }
+}
+
+fn scan_expression(expression: &ast::Expression, nested: bool, bindings: &mut Bindings) {
+    match &expression.kind {
+        ast::ExpressionKind::Identifier(name) => {
+            if nested {
+                bindings.captured.insert(name.clone());
+            }
+        }
+        ast::ExpressionKind::Boolean(_)
+        | ast::ExpressionKind::IntegerLiteral(_)
+        | ast::ExpressionKind::BigIntegerLiteral(_)
+        | ast::ExpressionKind::FloatLiteral(_)
+        | ast::ExpressionKind::StringLiteral(_) => (),
+        ast::ExpressionKind::PrefixExpression { right, .. } => {
+            scan_expression(right, nested, bindings)
+        }
+        ast::ExpressionKind::InfixExpression { left, right, .. } => {
+            scan_expression(left, nested, bindings);
+            scan_expression(right, nested, bindings);
+        }
+        ast::ExpressionKind::IfExpression {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            scan_expression(condition, nested, bindings);
+            scan_block(consequence, nested, bindings);
+            if let Some(alternative) = alternative {
+                scan_block(alternative, nested, bindings);
+            }
+        }
+        ast::ExpressionKind::FunctionLiteral { body, .. } => scan_block(body, true, bindings),
+        ast::ExpressionKind::While { condition, body } => {
+            scan_expression(condition, nested, bindings);
+            scan_block(body, nested, bindings);
+        }
+        ast::ExpressionKind::For {
+            variable,
+            iterable,
+            body,
+        } => {
+            scan_expression(iterable, nested, bindings);
+            if !nested {
+                bindings.declare(variable);
+            }
+            scan_block(body, nested, bindings);
+        }
+        ast::ExpressionKind::CallExpression {
+            function,
+            arguments,
+        } => {
+            scan_expression(function, nested, bindings);
+            for argument in arguments {
+                scan_expression(argument, nested, bindings);
+            }
+        }
+        ast::ExpressionKind::TemplateString(parts) => {
+            for part in parts {
+                if let ast::TemplatePart::Expression(e) = part {
+                    scan_expression(e, nested, bindings);
+                }
+            }
+        }
+        ast::ExpressionKind::ArrayLiteral(elements) => {
+            for element in elements {
+                scan_expression(element, nested, bindings);
+            }
+        }
+        ast::ExpressionKind::IndexExpression { left, index } => {
+            scan_expression(left, nested, bindings);
+            scan_expression(index, nested, bindings);
+        }
+        ast::ExpressionKind::HashLiteral(pairs) => {
+            for pair in pairs {
+                scan_expression(&pair.key, nested, bindings);
+                scan_expression(&pair.value, nested, bindings);
+            }
+        }
+        ast::ExpressionKind::Assign { name, value, .. } => {
+            if nested {
+                bindings.captured.insert(name.clone());
+            }
+            scan_expression(value, nested, bindings);
+        }
+    }
+}
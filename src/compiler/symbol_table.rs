@@ -0,0 +1,186 @@
+use crate::ast::Identifier;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+    // 外側の関数のローカル変数を捕捉したもの
+    Free,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: Identifier,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub outer: Option<Box<SymbolTable>>,
+    store: HashMap<Identifier, Symbol>,
+    pub num_definitions: usize,
+    // 捕捉した外側のシンボル。Freeのindexはこの並びの位置
+    pub free_symbols: Vec<Symbol>,
+    // 関数の本体でletするが、まだその文に達していない名前。
+    // 内側の関数やループの次の繰り返しからは、letした後に参照されることがある
+    declared: HashMap<Identifier, Symbol>,
+    // 内側の関数から参照される名前。この名前のローカル変数はセルに入れ、
+    // 後のletや代入による変更をクロージャと共有する
+    pub cells: HashSet<Identifier>,
+    // letやforで定義するローカル変数のうち、値がまだ入っていないかもしれない名前。
+    // 関数の本体の一番外側でletした後は、必ず値が入っている
+    pub unset: HashSet<Identifier>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.outer.is_none()
+    }
+
+    // 同じスコープで定義済みの名前は同じ場所を使い回す
+    pub fn define(&mut self, name: &Identifier) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            if matches!(symbol.scope, SymbolScope::Global | SymbolScope::Local) {
+                return symbol.clone();
+            }
+        }
+        if let Some(symbol) = self.declared.remove(name) {
+            self.store.insert(name.clone(), symbol.clone());
+            return symbol;
+        }
+        let symbol = Symbol {
+            name: name.clone(),
+            scope: if self.is_global() {
+                SymbolScope::Global
+            } else {
+                SymbolScope::Local
+            },
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.store.insert(name.clone(), symbol.clone());
+        symbol
+    }
+
+    // 一番外側のスコープにグローバル変数として定義する
+    pub fn define_global(&mut self, name: &Identifier) -> Symbol {
+        match &mut self.outer {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    // 後でletする名前のローカル変数の場所を先に確保する。定義済みの名前ならNone
+    pub fn declare(&mut self, name: &Identifier) -> Option<Symbol> {
+        if self.store.contains_key(name) || self.declared.contains_key(name) {
+            return None;
+        }
+        let symbol = Symbol {
+            name: name.clone(),
+            scope: SymbolScope::Local,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.declared.insert(name.clone(), symbol.clone());
+        Some(symbol)
+    }
+
+    // 同じ変数は一度だけ捕捉する。同じ名前のローカル変数があれば、そちらを名前で引けるようにしておく
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let index = match self.free_symbols.iter().position(|s| *s == original) {
+            Some(index) => index,
+            None => {
+                self.free_symbols.push(original.clone());
+                self.free_symbols.len() - 1
+            }
+        };
+        let symbol = Symbol {
+            name: original.name,
+            scope: SymbolScope::Free,
+            index,
+        };
+        self.store
+            .entry(symbol.name.clone())
+            .or_insert_with(|| symbol.clone());
+        symbol
+    }
+
+    // 外側の関数のローカル変数が見つかったら、途中の各スコープで自由変数にする。
+    // まだletしていない名前も、値がないときは外側を読むのでここで見つけてよい
+    pub fn resolve(&mut self, name: &Identifier) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name).or_else(|| self.declared.get(name)) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global | SymbolScope::Builtin => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    // symbolの変数にletで値が入る前に、代わりに読み書きする外側のスコープの変数。
+    // 評価器で環境を外側へ辿るのと同じ順に探す
+    pub fn fallback(&mut self, symbol: &Symbol) -> Option<Symbol> {
+        let found = match symbol.scope {
+            SymbolScope::Local => self.outer.as_mut()?.resolve(&symbol.name)?,
+            SymbolScope::Free => {
+                let original = self.free_symbols[symbol.index].clone();
+                self.outer.as_mut()?.fallback(&original)?
+            }
+            _ => return None,
+        };
+        match found.scope {
+            SymbolScope::Global | SymbolScope::Builtin => Some(found),
+            _ => Some(self.define_free(found)),
+        }
+    }
+
+    // 値がまだ入っていないかもしれないローカル変数か、それを捕捉したものか
+    pub fn may_be_unset(&self, symbol: &Symbol) -> bool {
+        match symbol.scope {
+            SymbolScope::Local => self.unset.contains(&symbol.name),
+            SymbolScope::Free => match &self.outer {
+                Some(outer) => outer.may_be_unset(&self.free_symbols[symbol.index]),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // ローカル変数か、捕捉した外側のローカル変数がセルに入っているか
+    pub fn is_cell(&self, symbol: &Symbol) -> bool {
+        match symbol.scope {
+            SymbolScope::Local => self.cells.contains(&symbol.name),
+            SymbolScope::Free => match &self.outer {
+                Some(outer) => outer.is_cell(&self.free_symbols[symbol.index]),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // グローバル変数の名前をindexの順に返す
+    pub fn global_names(&self) -> Vec<Identifier> {
+        let mut names = vec![None; self.num_definitions];
+        for symbol in self.store.values() {
+            if symbol.scope == SymbolScope::Global {
+                names[symbol.index] = Some(symbol.name.clone());
+            }
+        }
+        names.into_iter().flatten().collect()
+    }
+}
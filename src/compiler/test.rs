@@ -0,0 +1,333 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::Identifier;
+    use crate::compiler::symbol_table::{Symbol, SymbolScope, SymbolTable};
+    use crate::compiler::Compiler;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use std::rc::Rc;
+
+    fn compile(input: &str) -> (String, Vec<String>) {
+        let (program, errors) = Parser::new(Lexer::new(input)).program();
+        assert!(errors.is_empty(), "parse errors: {:?}", errors);
+        let mut compiler = Compiler::new();
+        let main = compiler.compile(&program);
+        let constants = compiler
+            .constants()
+            .iter()
+            .map(|c| match &**c {
+                Object::CompiledFunction(function) => function.instructions.to_string(),
+                other => other.to_string(),
+            })
+            .collect();
+        (main.instructions.to_string(), constants)
+    }
+
+    fn name(name: &str) -> Identifier {
+        Rc::new(name.to_string())
+    }
+
+    #[test]
+    fn test_compile_expressions() {
+        assert_eq!(
+            compile("1 + 2; 3"),
+            (
+                "0000 Constant 0
+0005 Constant 1
+0010 Add
+0011 Pop
+0012 Constant 2
+0017 ReturnValue
+"
+                .to_string(),
+                vec!["1".to_string(), "2".to_string(), "3".to_string()]
+            )
+        );
+        assert_eq!(
+            compile("if (true) { 10 }").0,
+            "0000 True
+0001 JumpNotTruthy 16
+0006 Constant 0
+0011 Jump 17
+0016 Null
+0017 ReturnValue
+"
+        );
+        assert_eq!(
+            compile(r#""a${1}""#),
+            (
+                "0000 Constant 0\n0005 Constant 1\n0010 Concat 2\n0015 ReturnValue\n".to_string(),
+                vec!["a".to_string(), "1".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_compile_logical_operators() {
+        assert_eq!(
+            compile("true && false").0,
+            "0000 True
+0001 JumpNotTruthy 18
+0006 False
+0007 JumpNotTruthy 18
+0012 True
+0013 Jump 19
+0018 False
+0019 ReturnValue
+"
+        );
+        assert_eq!(
+            compile("true || false").0,
+            "0000 True
+0001 JumpNotTruthy 12
+0006 True
+0007 Jump 25
+0012 False
+0013 JumpNotTruthy 24
+0018 True
+0019 Jump 25
+0024 False
+0025 ReturnValue
+"
+        );
+    }
+
+    #[test]
+    fn test_compile_let_statements() {
+        assert_eq!(
+            compile("let a = 1; a").0,
+            "0000 Constant 0
+0005 SetGlobal 0
+0010 Null
+0011 Pop
+0012 GetGlobal 0
+0017 ReturnValue
+"
+        );
+        // 未定義の名前にもグローバル変数の場所を割り当て、後の定義で同じ場所を使う
+        assert_eq!(
+            compile("let f = fn() { b }; let b = 2; len").0,
+            "0000 Closure 0 0
+0007 SetGlobal 1
+0012 Null
+0013 Pop
+0014 Constant 1
+0019 SetGlobal 0
+0024 Null
+0025 Pop
+0026 GetBuiltin 0
+0028 ReturnValue
+"
+        );
+    }
+
+    #[test]
+    fn test_compile_closures() {
+        let (main, constants) = compile("fn(a) { fn(b) { let c = 1; a + b + c } }");
+        assert_eq!(main, "0000 Closure 2 0\n0007 ReturnValue\n");
+        assert_eq!(
+            constants[1],
+            "0000 Constant 0
+0005 SetLocal 1
+0008 Null
+0009 Pop
+0010 GetFree 0
+0013 GetCell
+0014 GetLocal 0
+0017 Add
+0018 GetLocal 1
+0021 Add
+0022 ReturnValue
+"
+        );
+        assert_eq!(
+            constants[2],
+            "0000 GetLocal 0\n0003 DefineCell 0\n0006 GetLocal 0\n0009 Closure 1 1\n0016 ReturnValue\n"
+        );
+
+        // 後でletする名前も、内側の関数からは先に用意したセルを通して参照できる。
+        // letする前に呼ばれたときは、外側のスコープの同じ名前を読む
+        let (_, constants) = compile("fn() { let f = fn() { g() }; let g = fn() { f() }; f }");
+        assert_eq!(
+            constants[0],
+            "0000 GetFree 0
+0003 GetCell
+0004 JumpIfDefined 14
+0009 GetGlobal 0
+0014 Call 0
+0017 ReturnValue
+"
+        );
+        assert_eq!(
+            constants[2],
+            "0000 GetLocal 0
+0003 DefineCell 0
+0006 GetLocal 1
+0009 DefineCell 1
+0012 GetLocal 1
+0015 Closure 0 1
+0022 DefineCell 0
+0025 Null
+0026 Pop
+0027 GetLocal 0
+0030 Closure 1 1
+0037 DefineCell 1
+0040 Null
+0041 Pop
+0042 GetLocal 0
+0045 GetCell
+0046 ReturnValue
+"
+        );
+    }
+
+    #[test]
+    fn test_compile_assignment() {
+        assert_eq!(
+            compile("let x = 1; x += 2").0,
+            "0000 Constant 0
+0005 SetGlobal 0
+0010 Null
+0011 Pop
+0012 GetGlobal 0
+0017 Constant 1
+0022 Add
+0023 AssignGlobal 0
+0028 GetGlobal 0
+0033 ReturnValue
+"
+        );
+        // 内側の関数から参照するローカル変数はセルに入れ、クロージャと共有する
+        let (_, constants) = compile("fn(a) { let b = 1; fn() { b = a } }");
+        assert_eq!(
+            constants[1],
+            "0000 GetFree 1
+0003 GetCell
+0004 GetFree 0
+0007 SetCell
+0008 GetFree 0
+0011 GetCell
+0012 ReturnValue
+"
+        );
+        assert_eq!(
+            constants[2],
+            "0000 GetLocal 0
+0003 DefineCell 0
+0006 GetLocal 1
+0009 DefineCell 1
+0012 Constant 0
+0017 DefineCell 1
+0020 Null
+0021 Pop
+0022 GetLocal 1
+0025 GetLocal 0
+0028 Closure 1 2
+0035 ReturnValue
+"
+        );
+        assert_eq!(compile("len = 1").1[1], "len");
+    }
+
+    #[test]
+    fn test_compile_loops() {
+        assert_eq!(
+            compile("while (true) { break; continue }").0,
+            "0000 EnterLoop
+0001 True
+0002 JumpNotTruthy 26
+0007 Unwind
+0008 Jump 26
+0013 Pop
+0014 Unwind
+0015 Jump 1
+0020 Pop
+0021 Jump 1
+0026 ExitLoop
+0027 Null
+0028 ReturnValue
+"
+        );
+        assert_eq!(
+            compile("for (x in [1]) { x }").0,
+            "0000 Constant 0
+0005 Array 1
+0010 Iter
+0011 EnterLoop
+0012 IterNext 33
+0017 SetGlobal 0
+0022 GetGlobal 0
+0027 Pop
+0028 Jump 12
+0033 ExitLoop
+0034 Pop
+0035 Pop
+0036 Null
+0037 ReturnValue
+"
+        );
+        // ループの変数も、繰り返しのたびに同じセルに入れ直す
+        let (_, constants) = compile("fn() { for (x in [1]) { fn() { x } } }");
+        assert_eq!(
+            constants[2],
+            "0000 GetLocal 0
+0003 DefineCell 0
+0006 Constant 0
+0011 Array 1
+0016 Iter
+0017 EnterLoop
+0018 IterNext 42
+0023 DefineCell 0
+0026 GetLocal 0
+0029 Closure 1 1
+0036 Pop
+0037 Jump 18
+0042 ExitLoop
+0043 Pop
+0044 Pop
+0045 Null
+0046 ReturnValue
+"
+        );
+    }
+
+    #[test]
+    fn test_symbol_table_resolves_free_variables() {
+        let mut global = SymbolTable::new();
+        global.define(&name("a"));
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define(&name("b"));
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define(&name("c"));
+
+        assert_eq!(
+            second.resolve(&name("a")).map(|s| s.scope),
+            Some(SymbolScope::Global)
+        );
+        assert_eq!(
+            second.resolve(&name("b")),
+            Some(Symbol {
+                name: name("b"),
+                scope: SymbolScope::Free,
+                index: 0,
+            })
+        );
+        assert_eq!(second.free_symbols[0].scope, SymbolScope::Local);
+        assert_eq!(
+            second.resolve(&name("c")).map(|s| s.scope),
+            Some(SymbolScope::Local)
+        );
+        assert_eq!(second.resolve(&name("d")), None);
+    }
+
+    #[test]
+    fn test_symbol_table_reuses_slots() {
+        let mut table = SymbolTable::new();
+        assert_eq!(table.define(&name("a")).index, 0);
+        assert_eq!(table.define(&name("b")).index, 1);
+        assert_eq!(table.define(&name("a")).index, 0);
+        assert_eq!(table.num_definitions, 2);
+        assert_eq!(table.global_names(), vec![name("a"), name("b")]);
+    }
+}
@@ -1,108 +1,228 @@
 use crate::{
     ast,
     object::{
+        builtins,
         environment::Environment,
-        {EvalError, Object},
+        error::Frame,
+        {big_to_float, EvalError, EvalErrorKind, Object},
     },
+    span::Span,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+mod test;
+
+// 無限再帰でメモリを使い果たさないよう、関数呼び出しの深さに上限を設ける。
+// VMも同じ上限を使い、どちらのバックエンドでも同じ深さでエラーになる
+pub const MAX_CALL_DEPTH: usize = 1 << 12;
+
+// 左シフトやべき乗で作れる整数の大きさの上限(ビット数)
+const MAX_RESULT_BITS: u64 = 1 << 20;
+
+thread_local! {
+    // 評価中の関数呼び出しの深さ
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// returnによる制御の移動はObjectとは別に扱い、エラーと同じように`?`で
+// 関数の境界(apply_function)かプログラムの先頭まで伝搬させる。
+// breakとcontinueは一番内側のループで止まる
+enum Signal {
+    Return(Rc<Object>),
+    Break,
+    Continue,
+    Error(EvalError),
+}
 
-pub fn eval(node: ast::Node, env: &mut Environment) -> Result<Rc<Object>, EvalError> {
-    match node {
-        ast::Node::Program(program) => eval_block_statemt(program, env),
+impl From<EvalError> for Signal {
+    fn from(error: EvalError) -> Self {
+        Signal::Error(error)
+    }
+}
+
+// 発生位置の決まっていないエラーにspanを付ける
+fn error_at(span: Span, error: EvalError) -> Signal {
+    Signal::Error(error.at(span))
+}
+
+pub fn eval(node: ast::Node, env: &Rc<RefCell<Environment>>) -> Result<Rc<Object>, EvalError> {
+    let result = match node {
+        ast::Node::Program(program) => eval_block_statemt(&program, env),
         // 使ってないようなのでコメントアウト
         // ast::Node::Statement(statement) => eval_statement(statement, env),
         // ast::Node::Expression(expression) => eval_expression(expression, env),
+    };
+    match result {
+        Ok(value) | Err(Signal::Return(value)) => Ok(value),
+        Err(Signal::Error(error)) => Err(error),
+        // ループの外のbreakとcontinueはパーサーがエラーにする
+        Err(Signal::Break | Signal::Continue) => unreachable!("break outside of a loop"),
     }
 }
 
 fn eval_block_statemt(
-    program: ast::Program,
-    env: &mut Environment,
-) -> Result<Rc<Object>, EvalError> {
+    program: &ast::Program,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, Signal> {
     let mut result = Rc::new(Object::Null);
     for hoge in program {
         result = eval_statement(hoge, env)?;
-
-        if let Object::ReturnValue(val) = &*result {
-            return Ok(val.clone());
-        }
     }
     Ok(result)
 }
 
 fn eval_statement(
-    statement: ast::Statement,
-    env: &mut Environment,
-) -> Result<Rc<Object>, EvalError> {
-    match statement {
-        ast::Statement::LetStatement { name, value } => {
+    statement: &ast::Statement,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, Signal> {
+    match &statement.kind {
+        ast::StatementKind::LetStatement { name, value } => {
             let value = eval_expression(value, env)?;
-            env.set(&name, value.clone());
+            env.borrow_mut().set(name, value);
             Ok(Rc::new(Object::Null))
         }
-        ast::Statement::Return { return_value } => {
+        ast::StatementKind::Return { return_value } => {
             let return_value = eval_expression(return_value, env)?;
-            Ok(Rc::new(Object::ReturnValue(return_value)))
+            Err(Signal::Return(return_value))
         }
-        ast::Statement::Expression { expression } => eval_expression(expression, env),
+        ast::StatementKind::Expression { expression } => eval_expression(expression, env),
+        ast::StatementKind::Break => Err(Signal::Break),
+        ast::StatementKind::Continue => Err(Signal::Continue),
     }
 }
 
 fn eval_expression(
-    expression: ast::Expression,
-    env: &mut Environment,
-) -> Result<Rc<Object>, EvalError> {
-    match expression {
-        ast::Expression::Identifier(identifier) => eval_identifier(identifier, env),
-        ast::Expression::Boolean(b) => Ok(Rc::new(Object::Boolean(b))),
-        ast::Expression::IntegerLiteral(i) => Ok(Rc::new(Object::Integer(i))),
-        ast::Expression::PrefixExpression { operator, right } => {
-            let right = eval_expression(*right, env)?;
-            eval_prefix_expression(operator, right)
+    expression: &ast::Expression,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, Signal> {
+    let span = expression.span;
+    match &expression.kind {
+        ast::ExpressionKind::Identifier(identifier) => {
+            eval_identifier(identifier, env).map_err(|e| error_at(span, e))
+        }
+        ast::ExpressionKind::Boolean(b) => Ok(Rc::new(Object::Boolean(*b))),
+        ast::ExpressionKind::IntegerLiteral(i) => Ok(Rc::new(Object::Integer(*i))),
+        ast::ExpressionKind::BigIntegerLiteral(i) => Ok(Rc::new(Object::BigInteger(i.clone()))),
+        ast::ExpressionKind::FloatLiteral(x) => Ok(Rc::new(Object::Float(*x))),
+        ast::ExpressionKind::PrefixExpression { operator, right } => {
+            let right = eval_expression(right, env)?;
+            eval_prefix_expression(operator.clone(), right).map_err(|e| error_at(span, e))
         }
-        ast::Expression::InfixExpression {
+        ast::ExpressionKind::InfixExpression {
             left,
             operator,
             right,
         } => {
-            let left = eval_expression(*left, env)?;
-            let right = eval_expression(*right, env)?;
-            eval_infix_expression(operator, left, right)
+            let left = eval_expression(left, env)?;
+            match (operator, is_truthy(&left)) {
+                (ast::InfixOperator::And, false) => return Ok(Rc::new(Object::Boolean(false))),
+                (ast::InfixOperator::Or, true) => return Ok(Rc::new(Object::Boolean(true))),
+                _ => (),
+            }
+            let right = eval_expression(right, env)?;
+            eval_infix_expression(operator.clone(), left, right).map_err(|e| error_at(span, e))
         }
-        ast::Expression::IfExpression {
+        ast::ExpressionKind::IfExpression {
             condition,
             consequence,
             alternative,
-        } => eval_if_expression(*condition, consequence, alternative, env),
-        ast::Expression::FunctionLiteral { parameters, body } => Ok(Rc::new(Object::Function {
+        } => eval_if_expression(condition, consequence, alternative.as_ref(), env),
+        ast::ExpressionKind::While { condition, body } => {
+            while is_truthy(&*eval_expression(condition, env)?) {
+                if !eval_loop_body(body, env)? {
+                    break;
+                }
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        ast::ExpressionKind::For {
+            variable,
+            iterable,
+            body,
+        } => {
+            let iterable_span = iterable.span;
+            let iterable = eval_expression(iterable, env)?;
+            let sequence = iteration_sequence(iterable).map_err(|e| error_at(iterable_span, e))?;
+            let mut i = 0;
+            while let Some(item) = iteration_item(&sequence, i) {
+                env.borrow_mut().set(variable, item);
+                if !eval_loop_body(body, env)? {
+                    break;
+                }
+                i += 1;
+            }
+            Ok(Rc::new(Object::Null))
+        }
+        ast::ExpressionKind::FunctionLiteral {
+            name,
             parameters,
             body,
-            env: env.clone(),
+        } => Ok(Rc::new(Object::Function {
+            name: name.clone(),
+            parameters: parameters.clone(),
+            body: body.clone(),
+            env: Rc::clone(env),
         })),
-        ast::Expression::CallExpression {
+        ast::ExpressionKind::CallExpression {
             function,
             arguments,
         } => {
-            let function = eval_expression(*function, env)?;
+            let function = eval_expression(function, env)?;
             let arguments = eval_expressions(arguments, env)?;
-            apply_function(function, arguments)
+            apply_function(function, arguments, span)
+        }
+        ast::ExpressionKind::StringLiteral(s) => Ok(Rc::new(Object::String(Rc::clone(s)))),
+        ast::ExpressionKind::TemplateString(parts) => {
+            let mut result = String::new();
+            for part in parts {
+                match part {
+                    ast::TemplatePart::Literal(s) => result.push_str(s),
+                    ast::TemplatePart::Expression(e) => {
+                        result.push_str(&eval_expression(e, env)?.to_string())
+                    }
+                }
+            }
+            Ok(Rc::new(Object::String(Rc::new(result))))
         }
-        ast::Expression::StringLiteral(s) => Ok(Rc::new(Object::String(s))),
-        ast::Expression::ArrayLiteral(elements) => {
+        ast::ExpressionKind::ArrayLiteral(elements) => {
             let elements = eval_expressions(elements, env)?;
             Ok(Rc::new(Object::Array(elements)))
         }
-        ast::Expression::IndexExpression { left, index } => {
-            let left = eval_expression(*left, env)?;
-            let index = eval_expression(*index, env)?;
-            eval_index_expression(left, index)
+        ast::ExpressionKind::IndexExpression { left, index } => {
+            let left = eval_expression(left, env)?;
+            let index = eval_expression(index, env)?;
+            eval_index_expression(left, index).map_err(|e| error_at(span, e))
+        }
+        ast::ExpressionKind::HashLiteral(pairs) => eval_hash_literal(pairs, env),
+        ast::ExpressionKind::Assign {
+            name,
+            operator,
+            value,
+        } => {
+            let value = match operator {
+                Some(operator) => {
+                    let current = eval_identifier(name, env).map_err(|e| error_at(span, e))?;
+                    let right = eval_expression(value, env)?;
+                    eval_infix_expression(operator.clone(), current, right)
+                        .map_err(|e| error_at(span, e))?
+                }
+                None => eval_expression(value, env)?,
+            };
+            if !env.borrow_mut().assign(name, Rc::clone(&value)) {
+                let error = EvalError::from(EvalErrorKind::UndefinedAssignment(Rc::clone(name)));
+                return Err(error_at(span, error));
+            }
+            Ok(value)
         }
-        ast::Expression::HashLiteral(pairs) => eval_hash_literal(pairs, env),
     }
 }
 
-fn eval_prefix_expression(
+pub fn eval_prefix_expression(
     operator: ast::PrefixOperator,
     right: Rc<Object>,
 ) -> Result<Rc<Object>, EvalError> {
@@ -113,51 +233,245 @@ fn eval_prefix_expression(
             _ => Ok(Rc::new(Object::Boolean(false))),
         },
         ast::PrefixOperator::Minus => match &*right {
-            Object::Integer(i) => Ok(Rc::new(Object::Integer(-i))),
-            _ => Err(format!("unknown operator: -{}", right)),
+            Object::Integer(i) => match i.checked_neg() {
+                Some(i) => Ok(Rc::new(Object::Integer(i))),
+                None => Ok(Rc::new(Object::integer(-BigInt::from(*i)))),
+            },
+            Object::BigInteger(i) => Ok(Rc::new(Object::integer(-i))),
+            Object::Float(x) => Ok(Rc::new(Object::Float(-x))),
+            _ => Err(EvalErrorKind::UnknownPrefixOperator {
+                operator,
+                right: right.type_name(),
+            }
+            .into()),
+        },
+        // 2の補数として反転する。`~x`は`-x - 1`と同じ
+        ast::PrefixOperator::BitNot => match &*right {
+            Object::Integer(i) => Ok(Rc::new(Object::Integer(!i))),
+            Object::BigInteger(i) => Ok(Rc::new(Object::integer(-i - 1))),
+            _ => Err(EvalErrorKind::UnknownPrefixOperator {
+                operator,
+                right: right.type_name(),
+            }
+            .into()),
         },
     }
 }
 
-fn eval_infix_expression(
+pub fn eval_infix_expression(
     operator: ast::InfixOperator,
     left: Rc<Object>,
     right: Rc<Object>,
 ) -> Result<Rc<Object>, EvalError> {
+    let unknown_operator = |operator| {
+        Err(EvalErrorKind::UnknownOperator {
+            left: left.type_name(),
+            operator,
+            right: right.type_name(),
+        }
+        .into())
+    };
+    // 評価器では右辺を評価する前に短絡するので、ここには両辺がそろって来る
+    if let ast::InfixOperator::And | ast::InfixOperator::Or = operator {
+        let result = match operator {
+            ast::InfixOperator::And => is_truthy(&left) && is_truthy(&right),
+            _ => is_truthy(&left) || is_truthy(&right),
+        };
+        return Ok(Rc::new(Object::Boolean(result)));
+    }
     match (&*left, &*right) {
-        (Object::Integer(l), Object::Integer(r)) => match operator {
-            ast::InfixOperator::Plus => Ok(Rc::new(Object::Integer(l + r))),
-            ast::InfixOperator::Minus => Ok(Rc::new(Object::Integer(l - r))),
-            ast::InfixOperator::Asterisk => Ok(Rc::new(Object::Integer(l * r))),
-            ast::InfixOperator::Slash => Ok(Rc::new(Object::Integer(l / r))),
-            ast::InfixOperator::Lt => Ok(Rc::new(Object::Boolean(l < r))),
-            ast::InfixOperator::Gt => Ok(Rc::new(Object::Boolean(l > r))),
-            ast::InfixOperator::Eq => Ok(Rc::new(Object::Boolean(l == r))),
-            ast::InfixOperator::NotEq => Ok(Rc::new(Object::Boolean(l != r))),
-        },
+        (Object::Integer(l), Object::Integer(r)) => {
+            if let Some(result) = compare(&operator, l, r) {
+                return Ok(Rc::new(Object::Boolean(result)));
+            }
+            let result = match operator {
+                ast::InfixOperator::Plus => l.checked_add(*r),
+                ast::InfixOperator::Minus => l.checked_sub(*r),
+                ast::InfixOperator::Asterisk => l.checked_mul(*r),
+                ast::InfixOperator::Slash | ast::InfixOperator::Percent if *r == 0 => {
+                    return Err(EvalErrorKind::DivisionByZero.into())
+                }
+                ast::InfixOperator::Slash => l.checked_div(*r),
+                ast::InfixOperator::Percent => l.checked_rem(*r),
+                // 負の指数では整数にならないので浮動小数点数で計算する
+                ast::InfixOperator::Power if *r < 0 => {
+                    return Ok(Rc::new(Object::Float((*l as f64).powf(*r as f64))))
+                }
+                ast::InfixOperator::Power => u32::try_from(*r).ok().and_then(|r| l.checked_pow(r)),
+                ast::InfixOperator::BitAnd => Some(l & r),
+                ast::InfixOperator::BitOr => Some(l | r),
+                ast::InfixOperator::BitXor => Some(l ^ r),
+                // シフトは範囲の確認も含めて多倍長整数で計算する
+                ast::InfixOperator::ShiftLeft | ast::InfixOperator::ShiftRight => None,
+                _ => unreachable!("not an arithmetic operator: {}", operator),
+            };
+            // i64に収まらなければ多倍長整数で計算し直す
+            match result {
+                Some(i) => Ok(Rc::new(Object::Integer(i))),
+                None => eval_big_integer_infix(operator, &BigInt::from(*l), &BigInt::from(*r)),
+            }
+        }
+        (
+            Object::Integer(_) | Object::BigInteger(_),
+            Object::Integer(_) | Object::BigInteger(_),
+        ) => eval_big_integer_infix(operator, &to_big_integer(&left), &to_big_integer(&right)),
+        // 整数と浮動小数点数が混ざったときは浮動小数点数にそろえる
+        (
+            Object::Integer(_) | Object::BigInteger(_) | Object::Float(_),
+            Object::Integer(_) | Object::BigInteger(_) | Object::Float(_),
+        ) => {
+            let (l, r) = (to_float(&left), to_float(&right));
+            if let Some(result) = compare(&operator, &l, &r) {
+                return Ok(Rc::new(Object::Boolean(result)));
+            }
+            let result = match operator {
+                ast::InfixOperator::Plus => l + r,
+                ast::InfixOperator::Minus => l - r,
+                ast::InfixOperator::Asterisk => l * r,
+                ast::InfixOperator::Slash => l / r,
+                ast::InfixOperator::Percent => l % r,
+                ast::InfixOperator::Power => l.powf(r),
+                ast::InfixOperator::BitAnd
+                | ast::InfixOperator::BitOr
+                | ast::InfixOperator::BitXor
+                | ast::InfixOperator::ShiftLeft
+                | ast::InfixOperator::ShiftRight => return unknown_operator(operator),
+                _ => unreachable!("not an arithmetic operator: {}", operator),
+            };
+            Ok(Rc::new(Object::Float(result)))
+        }
         (Object::Boolean(l), Object::Boolean(r)) => match operator {
             ast::InfixOperator::Eq => Ok(Rc::new(Object::Boolean(l == r))),
             ast::InfixOperator::NotEq => Ok(Rc::new(Object::Boolean(l != r))),
-            _ => Err(format!("unknown operator: {} {} {}", left, operator, right)),
+            _ => unknown_operator(operator),
         },
         (Object::String(l), Object::String(r)) => match operator {
             ast::InfixOperator::Plus => Ok(Rc::new(Object::String(Rc::new(format!("{}{}", l, r))))),
             ast::InfixOperator::Eq => Ok(Rc::new(Object::Boolean(l == r))),
             ast::InfixOperator::NotEq => Ok(Rc::new(Object::Boolean(l != r))),
-            _ => Err(format!("unknown operator: {} {} {}", left, operator, right)),
+            _ => unknown_operator(operator),
+        },
+        _ => Err(EvalErrorKind::TypeMismatch {
+            left: left.type_name(),
+            operator,
+            right: right.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn eval_big_integer_infix(
+    operator: ast::InfixOperator,
+    l: &BigInt,
+    r: &BigInt,
+) -> Result<Rc<Object>, EvalError> {
+    if let Some(result) = compare(&operator, l, r) {
+        return Ok(Rc::new(Object::Boolean(result)));
+    }
+    let result = match operator {
+        ast::InfixOperator::Plus => l + r,
+        ast::InfixOperator::Minus => l - r,
+        ast::InfixOperator::Asterisk => l * r,
+        ast::InfixOperator::Slash | ast::InfixOperator::Percent if r.is_zero() => {
+            return Err(EvalErrorKind::DivisionByZero.into())
+        }
+        ast::InfixOperator::Slash => l / r,
+        ast::InfixOperator::Percent => l % r,
+        ast::InfixOperator::Power if r.is_negative() => {
+            let (l, r) = (big_to_float(l), big_to_float(r));
+            return Ok(Rc::new(Object::Float(l.powf(r))));
+        }
+        // 0、1、-1は何乗しても大きくならない
+        ast::InfixOperator::Power if l.magnitude().is_one() || l.is_zero() => match l.sign() {
+            Sign::Minus if r.bit(0) => -BigInt::one(),
+            Sign::NoSign if !r.is_zero() => BigInt::zero(),
+            _ => BigInt::one(),
         },
-        _ => Err(format!("type mismatch: {} {} {}", left, operator, right)),
+        // 計算する前に、結果のビット数の下限がシフトと同じ上限を超えないか確かめる
+        ast::InfixOperator::Power => {
+            let bits = BigInt::from(l.bits() - 1) * r + 1;
+            match r.to_u32() {
+                Some(exponent) if bits <= BigInt::from(MAX_RESULT_BITS) => l.pow(exponent),
+                _ => {
+                    return Err(EvalErrorKind::OutOfRange {
+                        what: "exponent",
+                        value: r.to_string(),
+                    }
+                    .into())
+                }
+            }
+        }
+        ast::InfixOperator::BitAnd => l & r,
+        ast::InfixOperator::BitOr => l | r,
+        ast::InfixOperator::BitXor => l ^ r,
+        // シフト量は0以上。右シフトは負の数では-∞方向に丸める
+        ast::InfixOperator::ShiftLeft | ast::InfixOperator::ShiftRight if r.is_negative() => {
+            return Err(EvalErrorKind::OutOfRange {
+                what: "shift amount",
+                value: r.to_string(),
+            }
+            .into())
+        }
+        // シフト量ではなく結果の大きさで制限する
+        ast::InfixOperator::ShiftLeft => {
+            let bits = BigInt::from(l.bits()) + r;
+            match bits.to_u64() {
+                Some(b) if b <= MAX_RESULT_BITS => l << (b - l.bits()),
+                _ => {
+                    return Err(EvalErrorKind::OutOfRange {
+                        what: "shift result",
+                        value: format!("{} bits", bits),
+                    }
+                    .into())
+                }
+            }
+        }
+        // 値のビット数以上ずらすと0か-1になる
+        ast::InfixOperator::ShiftRight => l >> r.to_u64().unwrap_or(u64::MAX).min(l.bits()),
+        _ => unreachable!("not an arithmetic operator: {}", operator),
+    };
+    Ok(Rc::new(Object::integer(result)))
+}
+
+// 比較演算子ならその結果を返す
+fn compare<T: PartialOrd>(operator: &ast::InfixOperator, l: &T, r: &T) -> Option<bool> {
+    match operator {
+        ast::InfixOperator::Lt => Some(l < r),
+        ast::InfixOperator::Gt => Some(l > r),
+        ast::InfixOperator::LtEq => Some(l <= r),
+        ast::InfixOperator::GtEq => Some(l >= r),
+        ast::InfixOperator::Eq => Some(l == r),
+        ast::InfixOperator::NotEq => Some(l != r),
+        _ => None,
+    }
+}
+
+fn to_big_integer(number: &Object) -> BigInt {
+    match number {
+        Object::Integer(i) => BigInt::from(*i),
+        Object::BigInteger(i) => i.clone(),
+        _ => panic!("not an integer: {:?}", number),
+    }
+}
+
+fn to_float(number: &Object) -> f64 {
+    match number {
+        Object::Integer(i) => *i as f64,
+        // 大きすぎる値は±infになる
+        Object::BigInteger(i) => big_to_float(i),
+        Object::Float(x) => *x,
+        _ => panic!("not a number: {:?}", number),
     }
 }
 
 fn eval_if_expression(
-    condition: ast::Expression,
-    consequence: ast::BlockStatement,
-    alternative: Option<ast::BlockStatement>,
-    env: &mut Environment,
-) -> Result<Rc<Object>, EvalError> {
+    condition: &ast::Expression,
+    consequence: &ast::BlockStatement,
+    alternative: Option<&ast::BlockStatement>,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, Signal> {
     let condition = eval_expression(condition, env)?;
-    if is_truthy(condition) {
+    if is_truthy(&condition) {
         eval_block_statemt(consequence, env)
     } else {
         match alternative {
@@ -167,8 +481,52 @@ fn eval_if_expression(
     }
 }
 
-fn is_truthy(obj: Rc<Object>) -> bool {
-    match &*obj {
+// ループの本体を1回評価する。breakで抜けるときはfalseを返す
+fn eval_loop_body(
+    body: &ast::BlockStatement,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<bool, Signal> {
+    match eval_block_statemt(body, env) {
+        Ok(_) | Err(Signal::Continue) => Ok(true),
+        Err(Signal::Break) => Ok(false),
+        Err(signal) => Err(signal),
+    }
+}
+
+// for-inで順に取り出す値の並びにする。ハッシュはキーの、文字列は1文字ずつの
+// 文字列の配列にし、rangeは要素を作らずそのまま使う
+pub fn iteration_sequence(iterable: Rc<Object>) -> Result<Rc<Object>, EvalError> {
+    match &*iterable {
+        Object::Array(_) | Object::Range { .. } => Ok(iterable),
+        // HashMapの順序は実行ごとに変わるので、キーを並べ替えてから回す
+        Object::Hash(pairs) => {
+            let mut keys: Vec<_> = pairs.keys().cloned().collect();
+            keys.sort_by(|a, b| a.cmp_hash_key(b));
+            Ok(Rc::new(Object::Array(keys)))
+        }
+        Object::String(s) => Ok(Rc::new(Object::Array(
+            s.chars()
+                .map(|c| Rc::new(Object::String(Rc::new(c.to_string()))))
+                .collect(),
+        ))),
+        other => Err(EvalErrorKind::NotIterable(other.type_name()).into()),
+    }
+}
+
+// iteration_sequenceで作った並びのi番目の値。終わりに達したらNone
+pub fn iteration_item(sequence: &Object, i: usize) -> Option<Rc<Object>> {
+    match sequence {
+        Object::Array(elements) => elements.get(i).cloned(),
+        Object::Range { start, end } => {
+            let value = start.checked_add(i64::try_from(i).ok()?)?;
+            (value < *end).then(|| Rc::new(Object::Integer(value)))
+        }
+        _ => None,
+    }
+}
+
+pub fn is_truthy(obj: &Object) -> bool {
+    match obj {
         Object::Null => false,
         Object::Boolean(b) => *b,
         _ => true,
@@ -176,9 +534,9 @@ fn is_truthy(obj: Rc<Object>) -> bool {
 }
 
 fn eval_expressions(
-    expressions: Vec<ast::Expression>,
-    env: &mut Environment,
-) -> Result<Vec<Rc<Object>>, EvalError> {
+    expressions: &[ast::Expression],
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Vec<Rc<Object>>, Signal> {
     let mut result = Vec::new();
     for expression in expressions {
         let evaluated = eval_expression(expression, env)?;
@@ -190,31 +548,62 @@ fn eval_expressions(
 fn apply_function(
     function: Rc<Object>,
     arguments: Vec<Rc<Object>>,
-) -> Result<Rc<Object>, EvalError> {
+    span: Span,
+) -> Result<Rc<Object>, Signal> {
     match &*function {
         Object::Function {
+            name,
             parameters,
             body,
             env,
         } => {
-            let mut extended_env = new_enclosed_environment(env.clone());
-            for (i, param) in parameters.iter().enumerate() {
-                extended_env.set(&param, arguments[i].clone());
+            if parameters.len() != arguments.len() {
+                let error = EvalError::from(EvalErrorKind::ArityMismatch {
+                    expected: parameters.len(),
+                    got: arguments.len(),
+                });
+                return Err(error_at(span, error));
             }
-            let evaluated = eval_block_statemt(body.clone(), &mut extended_env)?;
-            if let Object::ReturnValue(val) = &*evaluated {
-                Ok(val.clone())
-            } else {
-                Ok(evaluated)
+            let depth = CALL_DEPTH.get();
+            if depth >= MAX_CALL_DEPTH {
+                let error = EvalError::from(EvalErrorKind::StackOverflow);
+                return Err(error_at(span, error));
+            }
+            let extended_env = new_enclosed_environment(Rc::clone(env));
+            for (param, argument) in parameters.iter().zip(arguments) {
+                extended_env.borrow_mut().set(param, argument);
+            }
+            // 評価器は呼び出しごとにRustのスタックを大きく使うので、
+            // 上限の深さまで届くよう足りなくなったらスタックを継ぎ足す
+            CALL_DEPTH.set(depth + 1);
+            let result = stacker::maybe_grow(1024 * 1024, 16 * 1024 * 1024, || {
+                eval_block_statemt(body, &extended_env)
+            });
+            CALL_DEPTH.set(depth);
+            // returnはここで止め、呼び出し元には値として返す。
+            // エラーには呼び出し履歴としてこの関数を積む
+            match result {
+                Ok(value) | Err(Signal::Return(value)) => Ok(value),
+                Err(Signal::Error(mut error)) => {
+                    error.trace.push(Frame {
+                        function: name.clone(),
+                        call_site: span,
+                    });
+                    Err(Signal::Error(error))
+                }
+                // 関数の本体はループの外として解析される
+                Err(Signal::Break | Signal::Continue) => unreachable!("break outside of a loop"),
             }
         }
-        // todo:後でサポート予定
-        // Object::BuiltinFunction(f) => Ok(f(arguments)),
-        _ => Err(format!("not a function: {}", function)),
+        Object::Builtin(builtin) => (builtin.func)(arguments).map_err(|e| error_at(span, e)),
+        _ => {
+            let error = EvalError::from(EvalErrorKind::NotAFunction(function.type_name()));
+            Err(error_at(span, error))
+        }
     }
 }
 
-fn eval_index_expression(left: Rc<Object>, index: Rc<Object>) -> Result<Rc<Object>, EvalError> {
+pub fn eval_index_expression(left: Rc<Object>, index: Rc<Object>) -> Result<Rc<Object>, EvalError> {
     match (&*left, &*index) {
         (Object::Array(elements), Object::Integer(i)) => {
             if *i < 0 || *i >= elements.len() as i64 {
@@ -222,6 +611,11 @@ fn eval_index_expression(left: Rc<Object>, index: Rc<Object>) -> Result<Rc<Objec
             }
             Ok(elements[*i as usize].clone())
         }
+        // i64に収まらない位置の要素はない
+        (Object::Array(_), Object::BigInteger(_)) => Ok(Rc::new(Object::Null)),
+        (Object::Hash(_), _) if !index.is_hashable() => {
+            Err(EvalErrorKind::UnusableAsHashKey(index.type_name()).into())
+        }
         (Object::Hash(pairs), _) => {
             if let Some(value) = pairs.get(&index) {
                 Ok(value.clone())
@@ -229,39 +623,56 @@ fn eval_index_expression(left: Rc<Object>, index: Rc<Object>) -> Result<Rc<Objec
                 Ok(Rc::new(Object::Null))
             }
         }
-        _ => Err(format!("index operator not supported: {}", left)),
+        _ => Err(EvalErrorKind::IndexNotSupported {
+            left: left.type_name(),
+            index: index.type_name(),
+        }
+        .into()),
     }
 }
 
+#[allow(clippy::mutable_key_type)]
 fn eval_hash_literal(
-    pairs: Vec<ast::HashPair>,
-    env: &mut Environment,
-) -> Result<Rc<Object>, EvalError> {
+    pairs: &[ast::HashPair],
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, Signal> {
     let mut result = HashMap::new();
     for pair in pairs {
-        let key = eval_expression(pair.key, env)?;
-        let value = eval_expression(pair.value, env)?;
+        let span = pair.key.span;
+        let key = eval_expression(&pair.key, env)?;
+        if !key.is_hashable() {
+            let error = EvalError::from(EvalErrorKind::UnusableAsHashKey(key.type_name()));
+            return Err(error_at(span, error));
+        }
+        let value = eval_expression(&pair.value, env)?;
         result.insert(key, value);
     }
     Ok(Rc::new(Object::Hash(result)))
 }
 
-fn eval_identifier(name: Rc<String>, env: &mut Environment) -> Result<Rc<Object>, EvalError> {
-    match env.get(&name) {
-        Some(o) => Ok(o),
-        None => Err(format!("identifier not found: {}", name)),
+fn eval_identifier(
+    name: &Rc<String>,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<Rc<Object>, EvalError> {
+    if let Some(o) = env.borrow().get(name) {
+        return Ok(o);
+    }
+    match builtins::lookup(name) {
+        Some(builtin) => Ok(builtin),
+        None => Err(EvalErrorKind::UnknownIdentifier(Rc::clone(name)).into()),
     }
 }
 
-pub fn new_enclosed_environment(outer: Environment) -> Environment {
-    let mut env = new_environment();
-    env.outer = Some(Rc::new(RefCell::new(outer)));
-    env
+pub fn new_enclosed_environment(outer: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+    Rc::new(RefCell::new(Environment {
+        store: HashMap::new(),
+        outer: Some(outer),
+    }))
 }
 
-pub fn new_environment() -> Environment {
-    Environment {
+pub fn new_environment() -> Rc<RefCell<Environment>> {
+    Rc::new(RefCell::new(Environment {
         store: HashMap::new(),
         outer: None,
-    }
+    }))
 }
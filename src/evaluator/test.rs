@@ -0,0 +1,497 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::{InfixOperator, Node, PrefixOperator};
+    use crate::evaluator::{eval, new_environment};
+    use crate::lexer::Lexer;
+    use crate::object::{EvalError, EvalErrorKind, Object};
+    use crate::parser::Parser;
+    use std::rc::Rc;
+
+    fn eval_program(input: &str) -> Result<Rc<Object>, EvalError> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let (program, errors) = parser.program();
+        assert!(errors.is_empty(), "parse errors: {:?}", errors);
+        let env = new_environment();
+        eval(Node::Program(program), &env)
+    }
+
+    fn eval_test(input: &str) -> Result<String, String> {
+        eval_program(input)
+            .map(|o| o.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    fn assert_eval(input: &str, expected: &str) {
+        assert_eq!(
+            eval_test(input),
+            Ok(expected.to_string()),
+            "input: {}",
+            input
+        );
+    }
+
+    fn assert_eval_error(input: &str, expected: &str) {
+        assert_eq!(
+            eval_test(input),
+            Err(expected.to_string()),
+            "input: {}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        assert_eval(r#"len("")"#, "0");
+        assert_eval(r#"len("four")"#, "4");
+        assert_eval(r#"len("こんにちは")"#, "5");
+        assert_eval("len([1, 2, 3])", "3");
+        assert_eval("first([1, 2, 3])", "1");
+        assert_eval("first([])", "null");
+        assert_eval("last([1, 2, 3])", "3");
+        assert_eval("rest([1, 2, 3])", "[2, 3]");
+        assert_eval("rest([])", "null");
+        assert_eval("push([1], 2)", "[1, 2]");
+        assert_eval("let a = [1]; push(a, 2); a", "[1]");
+        assert_eval(r#"puts("hello")"#, "null");
+    }
+
+    #[test]
+    fn test_builtin_errors() {
+        assert_eval_error(
+            "len(1)",
+            "1:1: argument to `len` must be STRING, ARRAY or RANGE, got INTEGER",
+        );
+        assert_eval_error(
+            r#"len("one", "two")"#,
+            "1:1: wrong number of arguments. got=2, want=1",
+        );
+        assert_eval_error(
+            "first(1)",
+            "1:1: argument to `first` must be ARRAY, got INTEGER",
+        );
+        assert_eval_error(
+            "push(1, 1)",
+            "1:1: argument to `push` must be ARRAY, got INTEGER",
+        );
+        assert_eval_error(
+            "fn(x, y) { x }(1)",
+            "1:1: wrong number of arguments. got=1, want=2",
+        );
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        assert_eval("1.5 + 2.25", "3.75");
+        assert_eval("-2.5 * 2", "-5.0");
+        assert_eval("1 + 0.5", "1.5");
+        assert_eval("7 / 2", "3");
+        assert_eval("7 / 2.0", "3.5");
+        assert_eval("1e3", "1000.0");
+        assert_eval("1 == 1.0", "true");
+        assert_eval("2 > 1.5", "true");
+        assert_eval("0.5 != 0.5", "false");
+        assert_eval_error("1.5 + true", "1:1: type mismatch: FLOAT + BOOLEAN");
+        assert_eval_error("{0.5: 1}", "1:2: unusable as hash key: FLOAT");
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eval("-7 / 2", "-3");
+        assert_eval("1.0 / 0", "inf");
+        assert_eval_error("1 / 0", "1:1: division by zero");
+        assert_eval_error("let x = 0;\n5 / x", "2:1: division by zero");
+        assert_eval_error("100000000000000000000 / 0", "1:1: division by zero");
+    }
+
+    #[test]
+    fn test_comparison_and_arithmetic_operators() {
+        assert_eval(
+            "[1 <= 1, 2 <= 1, 1 >= 1.5, 2.5 >= 2]",
+            "[true, false, false, true]",
+        );
+        assert_eval(
+            "[7 % 3, -7 % 3, 7.5 % 2, 9223372036854775808 % 10]",
+            "[1, -1, 1.5, 8]",
+        );
+        assert_eval(
+            "[2 ** 10, 2 ** -1, 2.0 ** 0.5 > 1.41, -2 ** 2]",
+            "[1024, 0.5, true, -4]",
+        );
+        assert_eval("2 ** 3 ** 2", "512");
+        assert_eval("2 ** 64", "18446744073709551616");
+        assert_eval_error("1 % 0", "1:1: division by zero");
+        assert_eval_error("2 ** 4294967296", "1:1: exponent out of range: 4294967296");
+        // 結果がシフトと同じ1048576ビットを超えるべき乗は、計算する前にエラーにする
+        assert_eval("(2 ** 1048575) >> 1048574", "2");
+        assert_eval_error("2 ** 1048576", "1:1: exponent out of range: 1048576");
+        assert_eval_error("7 ** 4000000000", "1:1: exponent out of range: 4000000000");
+        assert_eval(
+            "[1 ** 99999999999999999999, (-1) ** 99999999999999999999, (-1) ** 4000000000, 0 ** 4000000000, 0 ** 0]",
+            "[1, -1, 1, 0, 1]",
+        );
+        assert_eval_error(r#""a" <= "b""#, "1:1: unknown operator: STRING <= STRING");
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eval(
+            "[0b1100 & 0b1010, 0b1100 | 0b1010, 0b1100 ^ 0b1010, ~0, ~-1]",
+            "[8, 14, 6, -1, 0]",
+        );
+        assert_eval(
+            "[1 << 4, 0xff >> 4, -16 >> 2, -1 >> 63]",
+            "[16, 15, -4, -1]",
+        );
+        assert_eval("1 << 63", "9223372036854775808");
+        assert_eval("(1 << 63 << 1) | 1", "18446744073709551617");
+        assert_eval("~(1 << 63)", "-9223372036854775809");
+        assert_eval("0x0f & 0xff << 4 | 1", "1");
+        // シフト量に上限はなく、左シフトは結果の大きさで制限する
+        assert_eval("1 << 64", "18446744073709551616");
+        assert_eval("1 << 62 << 40", "5070602400912917605986812821504");
+        assert_eval("(1 << 1048575) >> 1048575", "1");
+        assert_eval(
+            "[5 >> 70, -5 >> 70, (1 << 100) >> 99999999999999999999]",
+            "[0, -1, 0]",
+        );
+        assert_eval_error(
+            "1 << 1048576",
+            "1:1: shift result out of range: 1048577 bits",
+        );
+        assert_eval_error(
+            "1 << 62 << 1048600",
+            "1:1: shift result out of range: 1048663 bits",
+        );
+        assert_eval_error("1 >> -1", "1:1: shift amount out of range: -1");
+        assert_eval_error("1.0 & 1", "1:1: unknown operator: FLOAT & INTEGER");
+        assert_eval_error("~1.5", "1:1: unknown operator: ~FLOAT");
+        assert_eval_error("true | false", "1:1: unknown operator: BOOLEAN | BOOLEAN");
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert_eval(
+            "let n = if (false) { 1 }; [true && 1, true && n, false || 0, n || false]",
+            "[true, false, true, false]",
+        );
+        assert_eval("1 < 2 && 2 < 3 || false", "true");
+        // 右辺は必要なときだけ評価する
+        assert_eval("false && undefined", "false");
+        assert_eval("true || undefined()", "true");
+        assert_eval_error("true && undefined", "1:9: identifier not found: undefined");
+    }
+
+    #[test]
+    fn test_big_integers() {
+        assert_eval("9223372036854775807 + 1", "9223372036854775808");
+        assert_eval("-9223372036854775807 - 2", "-9223372036854775809");
+        assert_eval("3037000500 * 3037000500", "9223372037000250000");
+        assert_eval("-(-9223372036854775807 - 1)", "9223372036854775808");
+        assert_eval(
+            "let min = -9223372036854775807 - 1; min / -1",
+            "9223372036854775808",
+        );
+        assert_eval(
+            "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(25)",
+            "15511210043330985984000000",
+        );
+        // 結果がi64に収まれば元の整数に戻る
+        assert_eval("99999999999999999999 - 99999999999999999998", "1");
+        assert_eval("9223372036854775808 == 9223372036854775807 + 1", "true");
+        assert_eval("9223372036854775808 > 9223372036854775807", "true");
+        assert_eval("-9223372036854775808", "-9223372036854775808");
+        assert_eval("100000000000000000000 * 1.5", "1.5e20");
+        // f64で表せない大きさはinfになる
+        assert_eval(
+            "[float(10 ** 400), float(-(10 ** 400)), 10 ** 400 * 0.5, (10 ** 400) ** -1]",
+            "[inf, -inf, inf, 0.0]",
+        );
+        assert_eval(
+            r#"{18446744073709551616: "2^64"}[18446744073709551616]"#,
+            "2^64",
+        );
+        assert_eval("[1][18446744073709551616]", "null");
+        assert_eval_error(
+            "18446744073709551616 + true",
+            "1:1: type mismatch: INTEGER + BOOLEAN",
+        );
+    }
+
+    #[test]
+    fn test_conversion_builtins() {
+        assert_eval("int(3.99)", "3");
+        assert_eval("int(-3.99)", "-3");
+        assert_eval(r#"int(" 42 ")"#, "42");
+        assert_eval("float(3)", "3.0");
+        assert_eval(r#"float("2.5e1")"#, "25.0");
+        assert_eval(r#"str(1.0) + str([1, "a"])"#, "1.0[1, a]");
+        assert_eval_error(r#"int("4x")"#, r#"1:1: cannot convert "4x" to INTEGER"#);
+        assert_eval("int(1e20)", "100000000000000000000");
+        assert_eval(r#"int("-123456789012345678901")"#, "-123456789012345678901");
+        assert_eval("float(100000000000000000000)", "1e20");
+        assert_eval_error(r#"int(float("inf"))"#, "1:1: cannot convert inf to INTEGER");
+        assert_eval_error(r#"float("pi")"#, r#"1:1: cannot convert "pi" to FLOAT"#);
+        assert_eval_error(
+            "int([])",
+            "1:1: argument to `int` must be INTEGER, FLOAT or STRING, got ARRAY",
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eval(
+            "// 2乗する
+let square = fn(x) {
+  x * x /* 掛け算 */
+};
+square(3) // 9",
+            "9",
+        );
+    }
+
+    #[test]
+    fn test_template_strings() {
+        assert_eval(
+            r#"let name = "Ann"; let items = [1, 2]; "Hello ${name}, you have ${len(items)} items""#,
+            "Hello Ann, you have 2 items",
+        );
+        assert_eval(r#""${1.5 * 2}${true}${[1, "a"]}""#, "3.0true[1, a]");
+        assert_eval(r#""outer ${"inner ${1 + 1}"}""#, "outer inner 2");
+        assert_eval(r#""\${x}""#, "${x}");
+        assert_eval_error(
+            r#""a ${1 + true} b""#,
+            "1:6: type mismatch: INTEGER + BOOLEAN",
+        );
+    }
+
+    #[test]
+    fn test_raw_and_multiline_strings() {
+        assert_eval(r##"len(r"\d+\n") + len(r#"say "hi""#)"##, "13");
+        assert_eval(
+            "let f = fn() {\n  \"\"\"\n    a\n      b\\tc\n  \"\"\"\n};\nf()",
+            "a\n  b\tc",
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        assert_eval("let 単価 = 120; let 個数2 = 3; 単価 * 個数2", "360");
+        assert_eval_error(
+            "let 合計 = 1;\n合計 + 名前",
+            "2:6: identifier not found: 名前",
+        );
+    }
+
+    #[test]
+    fn test_builtins_can_be_shadowed() {
+        assert_eval("let len = fn(x) { 42 }; len([])", "42");
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        assert_eval(
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            "610",
+        );
+        assert_eval(
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             even(10)",
+            "true",
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        assert_eval(
+            "let adder = fn(x) { fn(y) { x + y } }; let add_two = adder(2); add_two(3)",
+            "5",
+        );
+        assert_eval("let get = fn() { later }; let later = 10; get()", "10");
+        assert_eval("let x = 1; let f = fn() { x }; let x = 2; f()", "2");
+    }
+
+    #[test]
+    fn test_assignment() {
+        assert_eval("let x = 1; x = x + 1; x += 10; x", "12");
+        assert_eval("let x = 1; let y = x = 5; [x, y]", "[5, 5]");
+        assert_eval(
+            "let a = 5; a <<= 2; a %= 7; a **= 2; a |= 64; a ^= 1; a &= 127; a >>= 1; a",
+            "50",
+        );
+        assert_eval(r#"let s = "a"; s += "b"; s"#, "ab");
+        // 定義している一番内側の環境の変数を書き換える
+        assert_eval("let x = 1; let f = fn() { x = 2 }; f(); x", "2");
+        assert_eval("let x = 1; let f = fn(x) { x = 2 }; f(0); x", "1");
+        assert_eval(
+            "let counter = fn() { let n = 0; fn() { n += 1 } }; let c = counter(); c(); c(); [c(), counter()()]",
+            "[3, 1]",
+        );
+        assert_eval_error("y = 1", "1:1: cannot assign to undefined variable: y");
+        assert_eval_error("len = 1", "1:1: cannot assign to undefined variable: len");
+        assert_eval_error("z += 1", "1:1: identifier not found: z");
+        assert_eval_error(
+            r#"let x = 1; x -= "a""#,
+            "1:12: type mismatch: INTEGER - STRING",
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eval(
+            "let i = 0; let s = 0; while (i < 5) { i += 1; s += i }; s",
+            "15",
+        );
+        assert_eval("while (false) { 1 }", "null");
+        assert_eval("let s = 0; for (x in [1, 2, 3]) { s += x }; s", "6");
+        assert_eval(
+            r#"let s = 0; for (k in {1: "a", 2: "b"}) { s += k }; s"#,
+            "3",
+        );
+        // ハッシュのキーは真偽値、整数、文字列の順で、それぞれ小さい順に回る
+        assert_eval(
+            r#"let r = []; for (k in {"b": 1, 10: 2, "a": 3, true: 4, 1 << 70: 5, -3: 6, false: 7}) { r = push(r, k) }; r"#,
+            "[false, true, -3, 10, 1180591620717411303424, a, b]",
+        );
+        assert_eval(
+            r#"let s = ""; for (c in "héllo") { s = c + s }; s"#,
+            "olléh",
+        );
+        assert_eval("let s = 0; for (i in range(1, 5)) { s += i }; s", "10");
+        assert_eval(
+            "[range(3), len(range(2, 7)), len(range(5, 1))]",
+            "[range(0, 3), 5, 0]",
+        );
+        assert_eval(
+            "let s = 0; for (i in range(10)) { if (i == 6) { break } if (i % 2 == 0) { continue } s += i }; s",
+            "9",
+        );
+        // breakとcontinueは一番内側のループに効く
+        assert_eval(
+            "let r = []; for (i in range(3)) { for (j in range(3)) { if (j > i) { break } r = push(r, j) } }; r",
+            "[0, 0, 1, 0, 1, 2]",
+        );
+        assert_eval(
+            "let f = fn(n) { for (i in range(n)) { if (i * i > n) { return i } } }; f(10)",
+            "4",
+        );
+        assert_eval_error("for (x in 5) { x }", "1:11: not iterable: INTEGER");
+        assert_eval_error(
+            "range(1.5)",
+            "1:1: argument to `range` must be INTEGER, got FLOAT",
+        );
+        assert_eval_error(
+            "range(1, 2, 3)",
+            "1:1: wrong number of arguments. got=3, want=2",
+        );
+    }
+
+    #[test]
+    fn test_return_statements() {
+        assert_eval("return 10; 9;", "10");
+        assert_eval("9; return 2 * 5; 9;", "10");
+        assert_eval("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10");
+        assert_eval(
+            "let f = fn(x) { if (x > 1) { return 1; } return 2; }; f(5)",
+            "1",
+        );
+        assert_eval(
+            "let f = fn() { if (true) { if (true) { return 1; } } 2 }; f() + 10",
+            "11",
+        );
+        assert_eval(
+            "let inner = fn() { return 1; }; let outer = fn() { inner(); 2 }; outer()",
+            "2",
+        );
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eval_error("5 + true;", "1:1: type mismatch: INTEGER + BOOLEAN");
+        assert_eval_error(
+            "let a = 1;\nlet b = a + foo;",
+            "2:13: identifier not found: foo",
+        );
+        assert_eval_error(
+            "let f = fn() {\n  -true\n};\nf()",
+            "2:3: unknown operator: -BOOLEAN\n    in f called at 4:1",
+        );
+        assert_eval_error("[1, 2][0](1)", "1:1: not a function: INTEGER");
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let tests = vec![
+            (
+                "true + false",
+                EvalErrorKind::UnknownOperator {
+                    left: "BOOLEAN",
+                    operator: InfixOperator::Plus,
+                    right: "BOOLEAN",
+                },
+            ),
+            (
+                "\"a\" - 1",
+                EvalErrorKind::TypeMismatch {
+                    left: "STRING",
+                    operator: InfixOperator::Minus,
+                    right: "INTEGER",
+                },
+            ),
+            (
+                "-\"a\"",
+                EvalErrorKind::UnknownPrefixOperator {
+                    operator: PrefixOperator::Minus,
+                    right: "STRING",
+                },
+            ),
+            (
+                "foobar",
+                EvalErrorKind::UnknownIdentifier(Rc::new("foobar".to_string())),
+            ),
+            ("true()", EvalErrorKind::NotAFunction("BOOLEAN")),
+            (
+                "1[0]",
+                EvalErrorKind::IndexNotSupported {
+                    left: "INTEGER",
+                    index: "INTEGER",
+                },
+            ),
+            ("{[1]: 2}", EvalErrorKind::UnusableAsHashKey("ARRAY")),
+            (
+                "{1: 2}[fn(x) { x }]",
+                EvalErrorKind::UnusableAsHashKey("FUNCTION"),
+            ),
+            (
+                "fn(x) { x }()",
+                EvalErrorKind::ArityMismatch {
+                    expected: 1,
+                    got: 0,
+                },
+            ),
+        ];
+        for (input, kind) in tests {
+            match eval_program(input) {
+                Err(error) => assert_eq!(error.kind, kind, "input: {}", input),
+                Ok(value) => panic!("input: {}, expected an error, got {}", input, value),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_trace() {
+        let input = "let inner = fn(x) { x + true };
+let outer = fn(x) { inner(x) };
+let run = fn() { fn() { outer(1) }() };
+run()";
+        let error = eval_program(input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1:21: type mismatch: INTEGER + BOOLEAN
+    in inner called at 2:21
+    in outer called at 3:25
+    in <anonymous> called at 3:18
+    in run called at 4:1"
+        );
+    }
+}
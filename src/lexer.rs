@@ -1,38 +1,113 @@
-use crate::token::Token;
+use crate::span::{FileId, Span};
+use crate::token::{SpannedToken, Token};
+use num_bigint::BigInt;
+use unicode_xid::UnicodeXID;
+mod source;
 mod test;
+use source::{StringChars, Utf8Chars};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufReader, Read};
+use std::iter::Peekable;
 use std::rc::Rc;
+use std::str::Chars;
 
-#[derive(Debug)]
+// 入力は全体を読み込まず、必要な分だけsourceから取り出す
 pub struct Lexer {
-    input: Vec<char>,
-    position: usize,
-    read_position: usize,
+    source: Box<dyn Iterator<Item = Result<char, String>>>,
+    // 先読みした、chより後の文字
+    lookahead: VecDeque<char>,
     ch: char,
+    at_eof: bool,
+    // 入力の読み込みやUTF-8の解釈に失敗したときのメッセージ。読めなくなった位置で報告する
+    read_error: Option<String>,
+    // start_captureから読み進めた文字
+    capture: Option<String>,
+    // chの位置
+    file: FileId,
+    line: u32,
+    column: u32,
+    offset: u32,
+    // コメントをTokenとして返すかどうか
+    keep_trivia: bool,
+    // 文字列に埋め込んだ式の中にいるとき、`${`ごとの閉じていない`{`の数。
+    // 対応する`}`で文字列の続きを読む
+    interpolations: Vec<usize>,
 }
 
 impl Lexer {
-    pub fn new(input: &String) -> Lexer {
-        let input: Vec<char> = input.chars().collect();
-        match input.get(0) {
-            Some(&ch) => Lexer {
-                input: input,
-                position: 0,
-                read_position: 1,
-                ch: ch,
-            },
-            None => Lexer {
-                input: input,
-                position: 0,
-                read_position: 0,
-                ch: '\0',
-            },
-        }
+    pub fn new(input: &str) -> Lexer {
+        Self::from_source(Box::new(StringChars::new(input.to_string())))
+    }
+
+    // UTF-8のテキストを少しずつ読みながら字句解析する
+    pub fn from_reader(reader: impl Read + 'static) -> Lexer {
+        Self::from_source(Box::new(Utf8Chars::new(BufReader::new(reader))))
+    }
+
+    fn from_source(source: Box<dyn Iterator<Item = Result<char, String>>>) -> Lexer {
+        let mut lexer = Lexer {
+            source,
+            lookahead: VecDeque::new(),
+            ch: '\0',
+            at_eof: true,
+            read_error: None,
+            capture: None,
+            file: FileId::default(),
+            line: 1,
+            column: 1,
+            offset: 0,
+            keep_trivia: false,
+            interpolations: Vec::new(),
+        };
+        lexer.read_char();
+        lexer
+    }
+
+    // トークンの位置にどのファイルのものかを記録する
+    pub fn with_file(mut self, file: FileId) -> Lexer {
+        self.file = file;
+        self
+    }
+
+    // フォーマッタなどのために、読み飛ばさずにコメントも返すようにする
+    pub fn with_trivia(mut self) -> Lexer {
+        self.keep_trivia = true;
+        self
     }
 
-    pub fn next_token(&mut self) -> Token {
-        Self::sukip_white_spaces(self);
+    pub fn next_token(&mut self) -> SpannedToken {
+        loop {
+            Self::sukip_white_spaces(self);
 
-        let token = match self.ch {
+            let (mut line, mut column, mut start) = (self.line, self.column, self.offset);
+            let mut token = self.read_token();
+            if matches!(token, Token::Comment(_)) && !self.keep_trivia {
+                continue;
+            }
+            // 読めなくなった位置で途切れたトークンは、その位置で読めなかった理由に置き換える
+            if self.at_eof && matches!(token, Token::EOF | Token::Error(_)) {
+                if let Some(message) = self.read_error.take() {
+                    token = Token::Error(Rc::new(message));
+                    (line, column, start) = (self.line, self.column, self.offset);
+                }
+            }
+            return SpannedToken {
+                token,
+                span: Span {
+                    file: self.file,
+                    line,
+                    column,
+                    start,
+                    end: self.offset,
+                },
+            };
+        }
+    }
+
+    fn read_token(&mut self) -> Token {
+        let ch = self.ch;
+        let token = match ch {
             '=' => {
                 if self.peek_char() == '=' {
                     self.read_char();
@@ -41,8 +116,8 @@ impl Lexer {
                     Token::Assign
                 }
             }
-            '+' => Token::Plus,
-            '-' => Token::Minus,
+            '+' => self.with_assign(Token::Plus, Token::PlusAssign),
+            '-' => self.with_assign(Token::Minus, Token::MinusAssign),
             '!' => {
                 if self.peek_char() == '=' {
                     self.read_char();
@@ -51,27 +126,98 @@ impl Lexer {
                     Token::Bang
                 }
             }
-            '*' => Token::Asterisk,
-            '/' => Token::Slash,
-            '<' => Token::Lt,
-            '>' => Token::Gt,
+            '*' => {
+                if self.peek_char() == '*' {
+                    self.read_char();
+                    self.with_assign(Token::Power, Token::PowerAssign)
+                } else {
+                    self.with_assign(Token::Asterisk, Token::AsteriskAssign)
+                }
+            }
+            '%' => self.with_assign(Token::Percent, Token::PercentAssign),
+            '/' => match self.peek_char() {
+                '/' => self.read_line_comment(),
+                '*' => self.read_block_comment(),
+                _ => self.with_assign(Token::Slash, Token::SlashAssign),
+            },
+            '<' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::LtEq
+                }
+                '<' => {
+                    self.read_char();
+                    self.with_assign(Token::ShiftLeft, Token::ShiftLeftAssign)
+                }
+                _ => Token::Lt,
+            },
+            '>' => match self.peek_char() {
+                '=' => {
+                    self.read_char();
+                    Token::GtEq
+                }
+                '>' => {
+                    self.read_char();
+                    self.with_assign(Token::ShiftRight, Token::ShiftRightAssign)
+                }
+                _ => Token::Gt,
+            },
+            '&' => {
+                if self.peek_char() == '&' {
+                    self.read_char();
+                    Token::And
+                } else {
+                    self.with_assign(Token::Ampersand, Token::AmpersandAssign)
+                }
+            }
+            '|' => {
+                if self.peek_char() == '|' {
+                    self.read_char();
+                    Token::Or
+                } else {
+                    self.with_assign(Token::Pipe, Token::PipeAssign)
+                }
+            }
+            '^' => self.with_assign(Token::Caret, Token::CaretAssign),
+            '~' => Token::Tilde,
             ';' => Token::Semicolon,
             ':' => Token::Colon,
             ',' => Token::Comma,
             '(' => Token::LParen,
             ')' => Token::RParen,
-            '{' => Token::LBrace,
-            '}' => Token::RBrace,
-            '"' => Token::String(Rc::new(Self::read_string(self))),
+            '{' => {
+                if let Some(braces) = self.interpolations.last_mut() {
+                    *braces += 1;
+                }
+                Token::LBrace
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.read_string(true)
+                }
+                Some(braces) => {
+                    *braces -= 1;
+                    Token::RBrace
+                }
+                None => Token::RBrace,
+            },
+            '"' if self.peek_char() == '"' && self.peek_char_at(1) == '"' => {
+                self.read_multiline_string()
+            }
+            '"' => self.read_string(false),
+            'r' if self.is_raw_string_start() => self.read_raw_string(),
+            // 実行可能なスクリプトの先頭の`#!/usr/bin/env rust-monkey`は行コメントとして扱う
+            '#' if self.offset == 0 && self.peek_char() == '!' => self.read_line_comment(),
             '[' => Token::LBracket,
             ']' => Token::RBracket,
             '\0' => Token::EOF,
             _ => {
-                if Self::is_letter(self.ch) {
+                if Self::is_identifier_start(self.ch) {
                     let literal = Self::read_identifier(self);
                     return Token::lookup_ident(literal);
                 } else if Self::is_digit(self.ch) {
-                    return Token::Int(Self::read_number(self));
+                    return Self::read_number(self);
                 } else {
                     Token::Illegal(Rc::new(self.ch.to_string()))
                 }
@@ -88,56 +234,486 @@ impl Lexer {
         }
     }
 
+    // 演算子の直後に`=`があれば複合代入のトークンにする
+    fn with_assign(&mut self, operator: Token, assign: Token) -> Token {
+        if self.peek_char() == '=' {
+            self.read_char();
+            assign
+        } else {
+            operator
+        }
+    }
+
     fn read_char(&mut self) {
-        self.ch = self.peek_char();
-        self.position = self.read_position;
-        self.read_position += 1;
+        if !self.at_eof {
+            if self.ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+            self.offset += self.ch.len_utf8() as u32;
+            if let Some(capture) = &mut self.capture {
+                capture.push(self.ch);
+            }
+        }
+        self.fill_lookahead(0);
+        match self.lookahead.pop_front() {
+            // 位置はu32で持つので、末尾が収まらない文字の手前で入力を終わりにする。
+            // 行と桁は1から数えるので、末尾に1を足しても収まる範囲に限ればあふれない
+            Some(ch) if self.offset.checked_add(ch.len_utf8() as u32 + 1).is_none() => {
+                self.lookahead.clear();
+                self.stop_reading("input is too large (over 4 GiB)".to_string());
+            }
+            Some(ch) => {
+                self.ch = ch;
+                self.at_eof = false;
+                return;
+            }
+            None => (),
+        }
+        self.ch = '\0';
+        self.at_eof = true;
     }
 
-    fn peek_char(&self) -> char {
-        match self.input.get(self.read_position) {
+    fn peek_char(&mut self) -> char {
+        self.peek_char_at(0)
+    }
+
+    // peek_charのさらにn文字先
+    fn peek_char_at(&mut self, n: usize) -> char {
+        self.fill_lookahead(n);
+        match self.lookahead.get(n) {
             Some(&ch) => ch,
             None => '\0',
         }
     }
 
+    // n文字先まで先読みする。読み込みに失敗したら、そこで入力が終わったものとする
+    fn fill_lookahead(&mut self, n: usize) {
+        while self.lookahead.len() <= n {
+            match self.source.next() {
+                Some(Ok(ch)) => self.lookahead.push_back(ch),
+                Some(Err(message)) => self.stop_reading(message),
+                None => return,
+            }
+        }
+    }
+
+    // この後の入力を読まずに、先読みした分を読み終えたところでmessageを報告する
+    fn stop_reading(&mut self, message: String) {
+        self.read_error = Some(message);
+        self.source = Box::new(std::iter::empty());
+    }
+
+    // chから、end_captureを呼んだときのchの直前までの文字を集める
+    fn start_capture(&mut self) {
+        self.capture = Some(String::new());
+    }
+
+    fn end_capture(&mut self) -> String {
+        self.capture.take().unwrap_or_default()
+    }
+
     //未確認
     fn read_identifier(&mut self) -> String {
-        let position = self.position;
-        while Self::is_letter(self.ch) {
+        self.start_capture();
+        self.read_char();
+        while Self::is_identifier_continue(self.ch) {
             self.read_char();
         }
-        self.input[position..self.position].iter().collect()
+        self.end_capture()
     }
 
     fn is_digit(ch: char) -> bool {
-        '0' <= ch && ch <= '9'
+        ch.is_ascii_digit()
+    }
+
+    // `3`、`3.14`、`1e-3`、`2.5E10`、`0xff`、`0o17`、`0b1010`、`1_000_000`の形式。
+    // 数字に続く英数字もまとめて読み、`0xZZ`のような不正なリテラルは1つのエラーにする
+    fn read_number(&mut self) -> Token {
+        self.start_capture();
+        let is_decimal =
+            !(self.ch == '0' && matches!(self.peek_char(), 'x' | 'X' | 'o' | 'O' | 'b' | 'B'));
+        let mut seen_point = false;
+        let mut prev = self.ch;
+        self.read_char();
+        loop {
+            let continues = match self.ch {
+                ch if ch.is_ascii_alphanumeric() || ch == '_' => true,
+                '.' => is_decimal && !seen_point && Self::is_digit(self.peek_char()),
+                '+' | '-' => {
+                    is_decimal && matches!(prev, 'e' | 'E') && Self::is_digit(self.peek_char())
+                }
+                _ => false,
+            };
+            if !continues {
+                break;
+            }
+            if is_decimal && matches!(self.ch, '.' | 'e' | 'E') {
+                seen_point = true;
+            }
+            prev = self.ch;
+            self.read_char();
+        }
+        let literal = self.end_capture();
+        match parse_number(&literal) {
+            Ok(token) => token,
+            Err(message) => Token::Error(Rc::new(message)),
+        }
     }
 
-    fn read_number(&mut self) -> i64 {
-        let position = self.position;
-        while Self::is_digit(self.ch) {
+    fn is_eof(&self) -> bool {
+        self.at_eof
+    }
+
+    // 閉じる`"`か、式を埋め込む`${`までを読み、エスケープを解釈する。
+    // continuedのときは、埋め込んだ式を閉じる`}`の後から読む。
+    // 不正なエスケープがあっても、続きを正しく読めるよう文字列の終わりまでは進める
+    fn read_string(&mut self, continued: bool) -> Token {
+        let mut raw = String::new();
+        let mut interpolates = false;
+        loop {
             self.read_char();
+            let ch = self.ch;
+            match ch {
+                _ if self.is_eof() => {
+                    return Token::Error(Rc::new("unterminated string".to_string()))
+                }
+                '"' => break,
+                '$' if self.peek_char() == '{' => {
+                    self.read_char();
+                    self.interpolations.push(0);
+                    interpolates = true;
+                    break;
+                }
+                '\\' => {
+                    raw.push(self.ch);
+                    self.read_char();
+                    if self.is_eof() {
+                        return Token::Error(Rc::new("unterminated string".to_string()));
+                    }
+                    raw.push(self.ch);
+                }
+                ch => raw.push(ch),
+            }
+        }
+        let value = match unescape(&raw) {
+            Ok(value) => Rc::new(value),
+            Err(message) => return Token::Error(Rc::new(message)),
+        };
+        match (continued, interpolates) {
+            (false, false) => Token::String(value),
+            (false, true) => Token::TemplateHead(value),
+            (true, true) => Token::TemplateMiddle(value),
+            (true, false) => Token::TemplateTail(value),
         }
-        self.input[position..self.position]
-            .iter()
-            .collect::<String>()
-            .parse()
-            .unwrap()
     }
 
-    fn read_string(&mut self) -> String {
-        let position = self.position + 1;
+    // `"""`で囲んだ複数行の文字列。式は埋め込めない。
+    // エスケープは共通の字下げを取り除いてから解釈する
+    fn read_multiline_string(&mut self) -> Token {
+        self.read_char();
+        self.read_char();
+        let mut raw = String::new();
         loop {
             self.read_char();
-            if self.ch == '"' || self.ch == '\0' {
+            let ch = self.ch;
+            match ch {
+                _ if self.is_eof() => {
+                    return Token::Error(Rc::new("unterminated string".to_string()))
+                }
+                '"' if self.peek_char() == '"' && self.peek_char_at(1) == '"' => {
+                    self.read_char();
+                    self.read_char();
+                    break;
+                }
+                '\\' => {
+                    raw.push(self.ch);
+                    self.read_char();
+                    if self.is_eof() {
+                        return Token::Error(Rc::new("unterminated string".to_string()));
+                    }
+                    raw.push(self.ch);
+                }
+                ch => raw.push(ch),
+            }
+        }
+        match unescape(&strip_indent(&raw)) {
+            Ok(value) => Token::String(Rc::new(value)),
+            Err(message) => Token::Error(Rc::new(message)),
+        }
+    }
+
+    // `r"..."`のようにエスケープを解釈しない文字列。
+    // `r#"..."#`のように`#`で囲めば`"`も書ける
+    fn is_raw_string_start(&mut self) -> bool {
+        let mut n = 0;
+        while self.peek_char_at(n) == '#' {
+            n += 1;
+        }
+        self.ch == 'r' && self.peek_char_at(n) == '"'
+    }
+
+    fn read_raw_string(&mut self) -> Token {
+        let mut hashes = 0;
+        self.read_char();
+        while self.ch == '#' {
+            hashes += 1;
+            self.read_char();
+        }
+        self.read_char();
+        self.start_capture();
+        loop {
+            if self.is_eof() {
+                self.end_capture();
+                return Token::Error(Rc::new("unterminated raw string".to_string()));
+            }
+            if self.ch == '"' && (0..hashes).all(|n| self.peek_char_at(n) == '#') {
                 break;
             }
+            self.read_char();
+        }
+        let value = self.end_capture();
+        for _ in 0..hashes {
+            self.read_char();
+        }
+        Token::String(Rc::new(value))
+    }
+
+    // 行末の直前までを読む
+    fn read_line_comment(&mut self) -> Token {
+        self.start_capture();
+        while self.peek_char() != '\n' && self.peek_char() != '\0' {
+            self.read_char();
+        }
+        let mut comment = self.end_capture();
+        comment.push(self.ch);
+        Token::Comment(Rc::new(comment))
+    }
+
+    // 入れ子になった`/* */`の対応が取れるところまで読む
+    fn read_block_comment(&mut self) -> Token {
+        self.start_capture();
+        self.read_char();
+        let mut depth = 1;
+        while depth > 0 {
+            self.read_char();
+            match (self.ch, self.peek_char()) {
+                _ if self.is_eof() => {
+                    self.end_capture();
+                    return Token::Error(Rc::new("unterminated block comment".to_string()));
+                }
+                ('/', '*') => {
+                    self.read_char();
+                    depth += 1;
+                }
+                ('*', '/') => {
+                    self.read_char();
+                    depth -= 1;
+                }
+                _ => (),
+            }
+        }
+        let mut comment = self.end_capture();
+        comment.push(self.ch);
+        Token::Comment(Rc::new(comment))
+    }
+
+    // 識別子はUnicodeのXID_Startか`_`で始まり、XID_Continue(数字や`_`を含む)が続く
+    fn is_identifier_start(ch: char) -> bool {
+        ch == '_' || UnicodeXID::is_xid_start(ch)
+    }
+
+    fn is_identifier_continue(ch: char) -> bool {
+        UnicodeXID::is_xid_continue(ch)
+    }
+}
+
+// EOFの手前までのトークンを順に返す
+impl Iterator for Lexer {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        let token = self.next_token();
+        match token.token {
+            Token::EOF => None,
+            _ => Some(token),
+        }
+    }
+}
+
+impl fmt::Debug for Lexer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lexer")
+            .field("ch", &self.ch)
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_number(literal: &str) -> Result<Token, String> {
+    let (radix, name) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, "hexadecimal"),
+        Some("0o" | "0O") => (8, "octal"),
+        Some("0b" | "0B") => (2, "binary"),
+        _ => return parse_decimal(literal),
+    };
+    let digits = &literal[2..];
+    if digits.is_empty() {
+        return Err(format!("missing digits in {} literal `{}`", name, literal));
+    }
+    if let Some(ch) = digits.chars().find(|&c| c != '_' && !c.is_digit(radix)) {
+        return Err(format!(
+            "invalid digit `{}` in {} literal `{}`",
+            ch, name, literal
+        ));
+    }
+    check_separators(digits, literal)?;
+    Ok(integer_token(&digits.replace('_', ""), radix))
+}
+
+// 整数部、小数部、指数部に分けて確かめる
+fn parse_decimal(literal: &str) -> Result<Token, String> {
+    let digits_end = |s: &str| {
+        s.find(|c: char| !(c.is_ascii_digit() || c == '_'))
+            .unwrap_or(s.len())
+    };
+    let end = digits_end(literal);
+    let mut groups = vec![&literal[..end]];
+    let mut rest = &literal[end..];
+    let mut is_float = false;
+    if let Some(after) = rest.strip_prefix('.') {
+        let end = digits_end(after);
+        groups.push(&after[..end]);
+        rest = &after[end..];
+        is_float = true;
+    }
+    if let Some(after) = rest.strip_prefix(['e', 'E']) {
+        let after = after.strip_prefix(['+', '-']).unwrap_or(after);
+        let end = digits_end(after);
+        if end == 0 {
+            return Err(format!("missing digits in exponent of `{}`", literal));
         }
-        self.input[position..self.position].iter().collect()
+        groups.push(&after[..end]);
+        rest = &after[end..];
+        is_float = true;
     }
+    if !rest.is_empty() {
+        return Err(format!(
+            "invalid suffix `{}` on numeric literal `{}`",
+            rest, literal
+        ));
+    }
+    for group in groups {
+        check_separators(group, literal)?;
+    }
+    let cleaned = literal.replace('_', "");
+    if is_float {
+        // 大きすぎる値はinfになるだけで失敗しない
+        Ok(Token::Float(cleaned.parse().unwrap()))
+    } else {
+        Ok(integer_token(&cleaned, 10))
+    }
+}
+
+// `_`は数字と数字の間にだけ書ける
+fn check_separators(digits: &str, literal: &str) -> Result<(), String> {
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err(format!("misplaced `_` in numeric literal `{}`", literal));
+    }
+    Ok(())
+}
+
+fn integer_token(digits: &str, radix: u32) -> Token {
+    match i64::from_str_radix(digits, radix) {
+        Ok(i) => Token::Int(i),
+        Err(_) => Token::BigInt(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap()),
+    }
+}
+
+// `\`によるエスケープを解釈する。不正なものがあれば最初のエラーを返す
+fn unescape(raw: &str) -> Result<String, String> {
+    let mut value = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('$') => '$',
+            Some('u') => unescape_unicode(&mut chars)?,
+            Some(ch) => return Err(format!("invalid escape sequence: \\{}", ch)),
+            None => return Err("unterminated string".to_string()),
+        };
+        value.push(escaped);
+    }
+    Ok(value)
+}
 
-    fn is_letter(ch: char) -> bool {
-        ('a' <= ch && ch <= 'z') || ('A' <= ch && ch <= 'Z') || ch == '_'
+// `\u{1F600}`の`\u`より後を読む。桁は1から6まで
+fn unescape_unicode(chars: &mut Peekable<Chars>) -> Result<char, String> {
+    if chars.next_if_eq(&'{').is_none() {
+        return Err("invalid unicode escape: expected `{` after \\u".to_string());
+    }
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_hexdigit) {
+        digits.push(digit);
+    }
+    if chars.next_if_eq(&'}').is_none() {
+        return Err(format!("invalid unicode escape: \\u{{{}", digits));
     }
+    if digits.is_empty() || digits.len() > 6 {
+        return Err(format!("invalid unicode escape: \\u{{{}}}", digits));
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("invalid unicode escape: \\u{{{}}}", digits))
+}
+
+// `"""`の直後の改行と、閉じる`"""`の前の空白だけの行を除き、
+// 空白だけではない行に共通する先頭の空白を取り除く。
+// タブと空白は別の文字として比べ、まったく同じ並びの部分だけを取り除く
+fn strip_indent(raw: &str) -> String {
+    let raw = raw
+        .strip_prefix('\n')
+        .or_else(|| raw.strip_prefix("\r\n"))
+        .unwrap_or(raw);
+    let mut lines: Vec<&str> = raw.split('\n').collect();
+    if lines.len() > 1 && lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+        .reduce(|common, indent| {
+            let shared = common
+                .bytes()
+                .zip(indent.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            &common[..shared]
+        })
+        .map_or(0, str::len);
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                ""
+            } else {
+                &line[indent..]
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
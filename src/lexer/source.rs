@@ -0,0 +1,88 @@
+use std::io::{BufRead, ErrorKind};
+
+// Lexer::newに渡された文字列を1文字ずつ返す
+pub struct StringChars {
+    text: String,
+    position: usize,
+}
+
+impl StringChars {
+    pub fn new(text: String) -> Self {
+        StringChars { text, position: 0 }
+    }
+}
+
+impl Iterator for StringChars {
+    type Item = Result<char, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ch = self.text[self.position..].chars().next()?;
+        self.position += ch.len_utf8();
+        Some(Ok(ch))
+    }
+}
+
+// バイト列をUTF-8として少しずつ解釈しながら1文字ずつ返す。
+// 不正なバイト列や読み込みのエラーはメッセージにして返す
+pub struct Utf8Chars<R> {
+    reader: R,
+    // 次に読むバイトの位置
+    offset: usize,
+}
+
+impl<R: BufRead> Utf8Chars<R> {
+    pub fn new(reader: R) -> Self {
+        Utf8Chars { reader, offset: 0 }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, String> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => {
+                    let byte = buf.first().copied();
+                    if byte.is_some() {
+                        self.reader.consume(1);
+                        self.offset += 1;
+                    }
+                    return Ok(byte);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(format!("cannot read input: {}", e)),
+            }
+        }
+    }
+
+    fn read_char(&mut self) -> Result<Option<char>, String> {
+        let start = self.offset;
+        let invalid = || format!("invalid UTF-8 at byte {}", start);
+        let first = match self.read_byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        // 先頭のバイトで文字の長さが決まる
+        let width = match first {
+            0x00..=0x7F => return Ok(Some(first as char)),
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Err(invalid()),
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for byte in &mut bytes[1..width] {
+            *byte = self.read_byte()?.ok_or_else(invalid)?;
+        }
+        std::str::from_utf8(&bytes[..width])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map(Some)
+            .ok_or_else(invalid)
+    }
+}
+
+impl<R: BufRead> Iterator for Utf8Chars<R> {
+    type Item = Result<char, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_char().transpose()
+    }
+}
@@ -2,13 +2,16 @@
 mod tests {
     // use super::*;
     use crate::lexer::Lexer;
+    use crate::span::{FileId, Span};
+    use crate::token::SpannedToken;
     use crate::token::Token::{self};
+    use std::io::{self, Read};
     use std::rc::Rc;
 
     fn token_test(input: &str, expected_tokens: Vec<Token>) {
-        let mut lexer = Lexer::new(&input.to_string());
+        let mut lexer = Lexer::new(input);
         for expected_token in expected_tokens {
-            let token = lexer.next_token();
+            let token = lexer.next_token().token;
             assert_eq!(token, expected_token);
         }
     }
@@ -81,7 +84,7 @@ mod tests {
         };
 
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
 
         if (5 < 10) {
@@ -188,4 +191,576 @@ mod tests {
         ];
         token_test(input, expected_output);
     }
+
+    #[test]
+    fn test_lex_operators() {
+        token_test(
+            "a <= b >= c % d ** e && f || g & h | i ^ ~j << k >> l",
+            vec![
+                Token::Ident(Rc::new("a".to_string())),
+                Token::LtEq,
+                Token::Ident(Rc::new("b".to_string())),
+                Token::GtEq,
+                Token::Ident(Rc::new("c".to_string())),
+                Token::Percent,
+                Token::Ident(Rc::new("d".to_string())),
+                Token::Power,
+                Token::Ident(Rc::new("e".to_string())),
+                Token::And,
+                Token::Ident(Rc::new("f".to_string())),
+                Token::Or,
+                Token::Ident(Rc::new("g".to_string())),
+                Token::Ampersand,
+                Token::Ident(Rc::new("h".to_string())),
+                Token::Pipe,
+                Token::Ident(Rc::new("i".to_string())),
+                Token::Caret,
+                Token::Tilde,
+                Token::Ident(Rc::new("j".to_string())),
+                Token::ShiftLeft,
+                Token::Ident(Rc::new("k".to_string())),
+                Token::ShiftRight,
+                Token::Ident(Rc::new("l".to_string())),
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_compound_assignment() {
+        token_test(
+            "+= -= *= /= %= **= &= |= ^= <<= >>= == <= >= && ||",
+            vec![
+                Token::PlusAssign,
+                Token::MinusAssign,
+                Token::AsteriskAssign,
+                Token::SlashAssign,
+                Token::PercentAssign,
+                Token::PowerAssign,
+                Token::AmpersandAssign,
+                Token::PipeAssign,
+                Token::CaretAssign,
+                Token::ShiftLeftAssign,
+                Token::ShiftRightAssign,
+                Token::Eq,
+                Token::LtEq,
+                Token::GtEq,
+                Token::And,
+                Token::Or,
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_loop_keywords() {
+        token_test(
+            "while for in break continue inside",
+            vec![
+                Token::While,
+                Token::For,
+                Token::In,
+                Token::Break,
+                Token::Continue,
+                Token::Ident(Rc::new("inside".to_string())),
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_unicode_identifiers() {
+        token_test(
+            "let 合計 = x1 + _tmp2 + café + 名前3; €",
+            vec![
+                Token::Let,
+                Token::Ident(Rc::new("合計".to_string())),
+                Token::Assign,
+                Token::Ident(Rc::new("x1".to_string())),
+                Token::Plus,
+                Token::Ident(Rc::new("_tmp2".to_string())),
+                Token::Plus,
+                Token::Ident(Rc::new("café".to_string())),
+                Token::Plus,
+                Token::Ident(Rc::new("名前3".to_string())),
+                Token::Semicolon,
+                Token::Illegal(Rc::new("€".to_string())),
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_spans() {
+        let mut lexer = Lexer::new("let x = \"あい\";\n  x == 10");
+        let expected = vec![
+            (Token::Let, 1, 1, 0, 3),
+            (Token::Ident(Rc::new("x".to_string())), 1, 5, 4, 5),
+            (Token::Assign, 1, 7, 6, 7),
+            (Token::String(Rc::new("あい".to_string())), 1, 9, 8, 16),
+            (Token::Semicolon, 1, 13, 16, 17),
+            (Token::Ident(Rc::new("x".to_string())), 2, 3, 20, 21),
+            (Token::Eq, 2, 5, 22, 24),
+            (Token::Int(10), 2, 8, 25, 27),
+            (Token::EOF, 2, 10, 27, 27),
+        ];
+        for (token, line, column, start, end) in expected {
+            let spanned = lexer.next_token();
+            assert_eq!(spanned.token, token);
+            assert_eq!(
+                spanned.span,
+                Span {
+                    line,
+                    column,
+                    start,
+                    end,
+                    ..Span::default()
+                }
+            );
+        }
+
+        // with_fileで指定したファイルの番号がすべてのトークンに付く
+        let files: Vec<FileId> = Lexer::new("a + 1")
+            .with_file(FileId(2))
+            .map(|t| t.span.file)
+            .collect();
+        assert_eq!(files, vec![FileId(2); 3]);
+    }
+
+    #[test]
+    fn test_lex_skips_comments() {
+        let input = "// 足し算
+let a = 1; // 末尾のコメント
+/* ブロック /* 入れ子 */ まだコメント */ a / 2 /**/";
+        let expected_output = vec![
+            Token::Let,
+            Token::Ident(Rc::new("a".to_string())),
+            Token::Assign,
+            Token::Int(1),
+            Token::Semicolon,
+            Token::Ident(Rc::new("a".to_string())),
+            Token::Slash,
+            Token::Int(2),
+            Token::EOF,
+        ];
+        token_test(input, expected_output);
+    }
+
+    #[test]
+    fn test_lex_keeps_trivia() {
+        let mut lexer = Lexer::new("1 // one\n/* a /* b */ */ 2").with_trivia();
+        let expected = vec![
+            (Token::Int(1), 1, 1),
+            (Token::Comment(Rc::new("// one".to_string())), 1, 3),
+            (Token::Comment(Rc::new("/* a /* b */ */".to_string())), 2, 1),
+            (Token::Int(2), 2, 17),
+            (Token::EOF, 2, 18),
+        ];
+        for (token, line, column) in expected {
+            let spanned = lexer.next_token();
+            assert_eq!(spanned.token, token);
+            assert_eq!((spanned.span.line, spanned.span.column), (line, column));
+        }
+    }
+
+    #[test]
+    fn test_lex_shebang() {
+        token_test(
+            "#!/usr/bin/env rust-monkey\nlet x = 1;",
+            vec![
+                Token::Let,
+                Token::Ident(Rc::new("x".to_string())),
+                Token::Assign,
+                Token::Int(1),
+                Token::Semicolon,
+                Token::EOF,
+            ],
+        );
+        let tokens: Vec<Token> = Lexer::new("#!/bin/monkey\n1")
+            .with_trivia()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Comment(Rc::new("#!/bin/monkey".to_string())),
+                Token::Int(1),
+            ]
+        );
+        // 先頭以外の`#!`は不正な文字のまま
+        token_test(
+            "1\n#!x",
+            vec![
+                Token::Int(1),
+                Token::Illegal(Rc::new("#".to_string())),
+                Token::Bang,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_unterminated_block_comment() {
+        let unterminated = Token::Error(Rc::new("unterminated block comment".to_string()));
+        token_test(
+            "1 /* a /* b */",
+            vec![Token::Int(1), unterminated.clone(), Token::EOF],
+        );
+        token_test("/*/", vec![unterminated]);
+    }
+
+    #[test]
+    fn test_lex_string_escapes() {
+        let tests = vec![
+            (r#""a\nb\tc\r""#, "a\nb\tc\r"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""back\\slash""#, "back\\slash"),
+            (r#""nul\0""#, "nul\0"),
+            (r#""\u{41}\u{3042}\u{1F600}""#, "Aあ😀"),
+        ];
+        for (input, expected) in tests {
+            token_test(input, vec![Token::String(Rc::new(expected.to_string()))]);
+        }
+    }
+
+    #[test]
+    fn test_lex_template_strings() {
+        let s = |s: &str| Rc::new(s.to_string());
+        token_test(
+            r#""Hi ${name}, ${ {"a": "${x}"}["a"] }!" "\${x} $x""#,
+            vec![
+                Token::TemplateHead(s("Hi ")),
+                Token::Ident(s("name")),
+                Token::TemplateMiddle(s(", ")),
+                Token::LBrace,
+                Token::String(s("a")),
+                Token::Colon,
+                Token::TemplateHead(s("")),
+                Token::Ident(s("x")),
+                Token::TemplateTail(s("")),
+                Token::RBrace,
+                Token::LBracket,
+                Token::String(s("a")),
+                Token::RBracket,
+                Token::TemplateTail(s("!")),
+                Token::String(s("${x} $x")),
+                Token::EOF,
+            ],
+        );
+        token_test(
+            r#""${x} abc"#,
+            vec![
+                Token::TemplateHead(s("")),
+                Token::Ident(s("x")),
+                Token::Error(s("unterminated string")),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_raw_and_multiline_strings() {
+        let s = |s: &str| Rc::new(s.to_string());
+        token_test(
+            r##"r"C:\dir\${x}" r#"say "hi""# r"" r x"##,
+            vec![
+                Token::String(s(r"C:\dir\${x}")),
+                Token::String(s(r#"say "hi""#)),
+                Token::String(s("")),
+                Token::Ident(s("r")),
+                Token::Ident(s("x")),
+                Token::EOF,
+            ],
+        );
+        token_test(
+            "let q = \"\"\"
+                SELECT *
+                  FROM t\\t
+
+                WHERE a = \"x\" -- ${y}
+                \"\"\"; \"\"\"one \"line\" here\"\"\" \"\"",
+            vec![
+                Token::Let,
+                Token::Ident(s("q")),
+                Token::Assign,
+                Token::String(s("SELECT *\n  FROM t\t\n\nWHERE a = \"x\" -- ${y}")),
+                Token::Semicolon,
+                Token::String(s("one \"line\" here")),
+                Token::String(s("")),
+                Token::EOF,
+            ],
+        );
+        // タブと空白が混ざっていれば、先頭から同じ並びの部分だけを共通の字下げとする
+        token_test(
+            "\"\"\"\n\t  a\n\t\tb\n  \tc\n\t  d\n\"\"\" \"\"\"\n\t\ta\n\t\t b\n\t\t\"\"\" \"\"\"\n\t  a\n\t\tb\n\"\"\"",
+            vec![
+                Token::String(s("\t  a\n\t\tb\n  \tc\n\t  d")),
+                Token::String(s("a\n b")),
+                Token::String(s("  a\n\tb")),
+                Token::EOF,
+            ],
+        );
+        token_test(
+            r#"r#"abc" x"#,
+            vec![Token::Error(s("unterminated raw string"))],
+        );
+        token_test(
+            "\"\"\"abc\"\"",
+            vec![Token::Error(s("unterminated string"))],
+        );
+    }
+
+    #[test]
+    fn test_lex_string_errors() {
+        let tests = vec![
+            (r#""abc"#, "unterminated string"),
+            (r#""abc\"#, "unterminated string"),
+            (r#""\q""#, r"invalid escape sequence: \q"),
+            (
+                r#""\u41""#,
+                r"invalid unicode escape: expected `{` after \u",
+            ),
+            (r#""\u{}""#, r"invalid unicode escape: \u{}"),
+            (r#""\u{41""#, r"invalid unicode escape: \u{41"),
+            (r#""\u{1234567}""#, r"invalid unicode escape: \u{1234567}"),
+            (r#""\u{D800}""#, r"invalid unicode escape: \u{D800}"),
+        ];
+        for (input, message) in tests {
+            token_test(input, vec![Token::Error(Rc::new(message.to_string()))]);
+        }
+    }
+
+    #[test]
+    fn test_lex_continues_after_bad_escape() {
+        let mut lexer = Lexer::new(r#"let s = "\q x"; s"#);
+        let tokens: Vec<_> = (0..6).map(|_| lexer.next_token()).collect();
+        assert_eq!(
+            tokens[3].token,
+            Token::Error(Rc::new(r"invalid escape sequence: \q".to_string()))
+        );
+        assert_eq!((tokens[3].span.column, tokens[3].span.end), (9, 14));
+        assert_eq!(tokens[4].token, Token::Semicolon);
+        assert_eq!(tokens[5].token, Token::Ident(Rc::new("s".to_string())));
+    }
+
+    #[test]
+    fn test_lex_numbers() {
+        token_test(
+            "3 2.75 0.5e3 1e-3 2E+2 1.x 4.",
+            vec![
+                Token::Int(3),
+                Token::Float(2.75),
+                Token::Float(500.0),
+                Token::Float(0.001),
+                Token::Float(200.0),
+                Token::Int(1),
+                Token::Illegal(Rc::new(".".to_string())),
+                Token::Ident(Rc::new("x".to_string())),
+                Token::Int(4),
+                Token::Illegal(Rc::new(".".to_string())),
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_radix_and_separators() {
+        token_test(
+            "0xff 0XFF 0o17 0b1010 1_000_000 1_0.2_5e1_0 0x_ 0b1-1 0x7fff_ffff_ffff_ffff_f",
+            vec![
+                Token::Int(255),
+                Token::Int(255),
+                Token::Int(15),
+                Token::Int(10),
+                Token::Int(1_000_000),
+                Token::Float(10.25e10),
+                Token::Error(Rc::new(
+                    "misplaced `_` in numeric literal `0x_`".to_string(),
+                )),
+                Token::Int(1),
+                Token::Minus,
+                Token::Int(1),
+                Token::BigInt("147573952589676412927".parse().unwrap()),
+                Token::EOF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_lex_malformed_numbers() {
+        let tests = vec![
+            ("0xZZ", "invalid digit `Z` in hexadecimal literal `0xZZ`"),
+            ("0o8", "invalid digit `8` in octal literal `0o8`"),
+            ("0b102", "invalid digit `2` in binary literal `0b102`"),
+            ("0x", "missing digits in hexadecimal literal `0x`"),
+            ("1__000", "misplaced `_` in numeric literal `1__000`"),
+            ("1_", "misplaced `_` in numeric literal `1_`"),
+            ("1.5_e3", "misplaced `_` in numeric literal `1.5_e3`"),
+            ("7e", "missing digits in exponent of `7e`"),
+            ("123abc", "invalid suffix `abc` on numeric literal `123abc`"),
+        ];
+        for (input, message) in tests {
+            token_test(
+                input,
+                vec![Token::Error(Rc::new(message.to_string())), Token::EOF],
+            );
+        }
+    }
+
+    #[test]
+    fn test_lex_big_integer_literal() {
+        token_test(
+            "9223372036854775807 9223372036854775808 1",
+            vec![
+                Token::Int(i64::MAX),
+                Token::BigInt("9223372036854775808".parse().unwrap()),
+                Token::Int(1),
+            ],
+        );
+    }
+
+    // 1回の読み込みで1バイトしか返さないReader
+    struct OneByteReader(Vec<u8>, usize);
+
+    impl Read for OneByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.get(self.1) {
+                Some(&byte) if !buf.is_empty() => {
+                    buf[0] = byte;
+                    self.1 += 1;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lex_from_reader() {
+        let input = "let 名前 = \"héllo 🎉\" + r#\"\"raw\"\"#; /* 入れ子 /* ok */ */ 1.5e3";
+        let expected: Vec<SpannedToken> = Lexer::new(input).with_trivia().collect();
+        let streamed: Vec<SpannedToken> =
+            Lexer::from_reader(OneByteReader(input.as_bytes().to_vec(), 0))
+                .with_trivia()
+                .collect();
+        assert_eq!(streamed, expected);
+        let buffered: Vec<SpannedToken> =
+            Lexer::from_reader(io::Cursor::new(input.to_string())).collect();
+        assert_eq!(buffered.len(), expected.len() - 1);
+    }
+
+    #[test]
+    fn test_lex_iterator_stops_before_eof() {
+        let tokens: Vec<Token> = Lexer::new("a + 1").map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident(Rc::new("a".to_string())),
+                Token::Plus,
+                Token::Int(1),
+            ]
+        );
+        assert_eq!(Lexer::new("").next(), None);
+    }
+
+    #[test]
+    fn test_lex_invalid_utf8() {
+        let tests: Vec<(&[u8], &str)> = vec![
+            (b"a \xff", "invalid UTF-8 at byte 2"),
+            (b"a \xe3\x81", "invalid UTF-8 at byte 2"),
+            (b"ab \xc0\xaf", "invalid UTF-8 at byte 3"),
+            (b"\xed\xa0\x80", "invalid UTF-8 at byte 0"),
+        ];
+        for (input, message) in tests {
+            let tokens: Vec<Token> = Lexer::from_reader(input).map(|t| t.token).collect();
+            let errors: Vec<&Token> = tokens
+                .iter()
+                .filter(|t| matches!(t, Token::Error(_)))
+                .collect();
+            assert_eq!(
+                errors,
+                vec![&Token::Error(Rc::new(message.to_string()))],
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_lex_invalid_utf8_position() {
+        // 不正なバイトで途切れたトークンはそのバイトの位置のエラーになり、後ろは読まない
+        // (トークン, 桁, 開始位置)
+        type Tokens = Vec<(Token, u32, u32)>;
+        let tests: Vec<(&[u8], Tokens)> = vec![
+            (
+                b"let s = \"ab\xff\"; x",
+                vec![
+                    (Token::Let, 1, 0),
+                    (Token::Ident(Rc::new("s".to_string())), 5, 4),
+                    (Token::Assign, 7, 6),
+                    (
+                        Token::Error(Rc::new("invalid UTF-8 at byte 11".to_string())),
+                        12,
+                        11,
+                    ),
+                ],
+            ),
+            (
+                b"a /* \xff */ b",
+                vec![
+                    (Token::Ident(Rc::new("a".to_string())), 1, 0),
+                    (
+                        Token::Error(Rc::new("invalid UTF-8 at byte 5".to_string())),
+                        6,
+                        5,
+                    ),
+                ],
+            ),
+            (
+                b"1 +\n\xe3\x81 2",
+                vec![
+                    (Token::Int(1), 1, 0),
+                    (Token::Plus, 3, 2),
+                    (
+                        Token::Error(Rc::new("invalid UTF-8 at byte 4".to_string())),
+                        1,
+                        4,
+                    ),
+                ],
+            ),
+        ];
+        for (input, expected) in tests {
+            let tokens: Tokens = Lexer::from_reader(input)
+                .map(|t| (t.token, t.span.column, t.span.start))
+                .collect();
+            assert_eq!(tokens, expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_lex_input_too_large() {
+        // 4GiBの入力は作れないので、位置を上限の近くまで進めておく
+        let mut lexer = Lexer::new("ab cd");
+        lexer.offset = u32::MAX - 5;
+        let tokens: Vec<(Token, u32, u32)> =
+            lexer.map(|t| (t.token, t.span.start, t.span.end)).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    Token::Ident(Rc::new("ab".to_string())),
+                    u32::MAX - 5,
+                    u32::MAX - 3
+                ),
+                (
+                    Token::Ident(Rc::new("c".to_string())),
+                    u32::MAX - 2,
+                    u32::MAX - 1
+                ),
+                (
+                    Token::Error(Rc::new("input is too large (over 4 GiB)".to_string())),
+                    u32::MAX - 1,
+                    u32::MAX - 1
+                ),
+            ]
+        );
+    }
 }
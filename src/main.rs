@@ -1,19 +1,19 @@
 mod ast;
+mod cli;
+mod code;
+mod compiler;
 mod evaluator;
 mod lexer;
 mod object;
 mod parser;
 mod repl;
+mod runtime;
+mod span;
 mod token;
-use repl::ReplMode;
+mod vm;
+use std::{env, process};
 
 fn main() {
-    // usernameを表示
-    let username = whoami::username();
-    println!(
-        "Hello {}! This is the Monkey programming language!",
-        username
-    );
-    let repl_mode = ReplMode::Eval;
-    repl::start(repl_mode);
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(cli::run(&args));
 }
@@ -1,40 +1,149 @@
+pub mod builtins;
 pub mod environment;
+pub mod error;
 
 use crate::ast::BlockStatement;
 use crate::ast::Identifier;
+use crate::code::CompiledFunction;
+use crate::object::builtins::Builtin;
 use crate::object::environment::Environment;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
-// todo:後でサポート予定
-// type BuiltinFunction = fn(Vec<Rc<Object>>) -> Rc<Object>;
+pub use error::{EvalError, EvalErrorKind};
 
-pub type EvalError = String;
-
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Object {
     Null,
     Integer(i64),
+    // i64に収まらない整数。収まる値は必ずIntegerで表す
+    BigInteger(BigInt),
+    Float(f64),
     Boolean(bool),
     String(Rc<String>),
-    ReturnValue(Rc<Object>),
     Function {
+        name: Option<Identifier>,
         parameters: Vec<Identifier>,
         body: BlockStatement,
-        env: Environment,
+        env: Rc<RefCell<Environment>>,
     },
-    // todo:後でサポート予定
-    // BuiltinFunction(BuiltinFunction),
+    Builtin(Builtin),
     Array(Vec<Rc<Object>>),
     Hash(HashMap<Rc<Object>, Rc<Object>>),
+    // startからendの手前までの整数の並び。for-inで要素を作らずに繰り返せる
+    Range {
+        start: i64,
+        end: i64,
+    },
+    // コンパイラの定数表にだけ現れ、実行時にはClosureに包まれる
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    // VMで、代入されるローカル変数を入れてクロージャと共有する入れ物。
+    // 読み書きの命令が中身を取り出すので、値として現れることはない
+    Cell(RefCell<Rc<Object>>),
+    // VMで、letをまだ実行していないローカル変数の中身。
+    // 読み書きの命令が確かめて外側のスコープの変数を使うので、値として現れることはない
+    Unset,
+}
+
+// Floatは反射律を満たさないが、ハッシュのキーにはならないのでEqとして扱ってよい
+impl Eq for Object {}
+
+// VMの関数の値。生成時に捕捉した自由変数の値を持つ
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Rc<Object>>,
+}
+
+impl Object {
+    // i64に収まればInteger、収まらなければBigIntegerにする
+    pub fn integer(value: BigInt) -> Object {
+        match i64::try_from(&value) {
+            Ok(i) => Object::Integer(i),
+            Err(_) => Object::BigInteger(value),
+        }
+    }
+
+    // ハッシュのキーの並び順。真偽値、整数、文字列の順で、同じ型の中では値の小さい順
+    pub fn cmp_hash_key(&self, other: &Object) -> Ordering {
+        fn rank(key: &Object) -> u8 {
+            match key {
+                Object::Boolean(_) => 0,
+                Object::Integer(_) | Object::BigInteger(_) => 1,
+                Object::String(_) => 2,
+                _ => panic!("unhashable type: {:?}", key),
+            }
+        }
+        fn to_big(key: &Object) -> BigInt {
+            match key {
+                Object::Integer(i) => BigInt::from(*i),
+                Object::BigInteger(i) => i.clone(),
+                _ => unreachable!(),
+            }
+        }
+        match (self, other) {
+            (Object::Boolean(a), Object::Boolean(b)) => a.cmp(b),
+            (Object::Integer(a), Object::Integer(b)) => a.cmp(b),
+            (Object::String(a), Object::String(b)) => a.cmp(b),
+            (
+                Object::Integer(_) | Object::BigInteger(_),
+                Object::Integer(_) | Object::BigInteger(_),
+            ) => to_big(self).cmp(&to_big(other)),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+
+    // Hashの実装がある型だけがハッシュのキーになれる
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self,
+            Object::Integer(_) | Object::BigInteger(_) | Object::Boolean(_) | Object::String(_)
+        )
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Null => "NULL",
+            // 利用者からは大きさで区別できないよう同じ型名にする
+            Object::Integer(_) | Object::BigInteger(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            // どちらのバックエンドでも同じエラーになるよう、関数は同じ型名にする
+            Object::Function { .. } | Object::CompiledFunction(_) | Object::Closure(_) => {
+                "FUNCTION"
+            }
+            Object::Builtin(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Range { .. } => "RANGE",
+            Object::Cell(cell) => cell.borrow().type_name(),
+            Object::Unset => "UNSET",
+        }
+    }
+}
+
+// f64で表せない大きさの整数は、浮動小数点数の桁あふれと同じく±infにする
+pub fn big_to_float(value: &BigInt) -> f64 {
+    value.to_f64().unwrap_or(if value.is_negative() {
+        f64::NEG_INFINITY
+    } else {
+        f64::INFINITY
+    })
 }
 
 impl Hash for Object {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
             Object::Integer(i) => i.hash(state),
+            Object::BigInteger(i) => i.hash(state),
             Object::Boolean(b) => b.hash(state),
             Object::String(s) => s.hash(state),
             _ => panic!("unhashable type: {:?}", self),
@@ -47,16 +156,15 @@ impl fmt::Display for Object {
         match self {
             Object::Null => write!(f, "null"),
             Object::Integer(i) => write!(f, "{}", i),
+            Object::BigInteger(i) => write!(f, "{}", i),
+            // 整数と区別できるよう`3.0`のように小数点を付けて表示する
+            Object::Float(x) => write!(f, "{:?}", x),
             Object::Boolean(b) => write!(f, "{}", b),
             Object::String(s) => write!(f, "{}", s),
-            Object::ReturnValue(o) => write!(f, "{}", o),
-            Object::Function {
-                parameters: _,
-                body: _,
-                env: _,
-            } => write!(f, "Function"),
-            // todo:後でサポート予定
-            // Object::BuiltinFunction(_) => write!(f, "BuiltinFunction"),
+            Object::Function { .. } | Object::CompiledFunction(_) | Object::Closure(_) => {
+                write!(f, "Function")
+            }
+            Object::Builtin(b) => write!(f, "builtin function: {}", b.name),
             Object::Array(v) => {
                 let mut s = String::from("[");
                 for (i, o) in v.iter().enumerate() {
@@ -65,20 +173,26 @@ impl fmt::Display for Object {
                     }
                     s.push_str(&format!("{}", o));
                 }
-                s.push_str("]");
+                s.push(']');
                 write!(f, "{}", s)
             }
             Object::Hash(h) => {
+                // HashMapの順は実行ごとに変わるので、キーの順に並べて表示する
+                let mut pairs: Vec<_> = h.iter().collect();
+                pairs.sort_by(|(a, _), (b, _)| a.cmp_hash_key(b));
                 let mut s = String::from("{");
-                for (i, (k, v)) in h.iter().enumerate() {
+                for (i, (k, v)) in pairs.into_iter().enumerate() {
                     if i != 0 {
-                        s.push_str(",");
+                        s.push(',');
                     }
                     s.push_str(&format!("{}: {}", k, v));
                 }
-                s.push_str("}");
+                s.push('}');
                 write!(f, "{}", s)
             }
+            Object::Range { start, end } => write!(f, "range({}, {})", start, end),
+            Object::Cell(cell) => write!(f, "{}", cell.borrow()),
+            Object::Unset => write!(f, "<unset>"),
         }
     }
 }
@@ -0,0 +1,263 @@
+use crate::object::{big_to_float, EvalError, EvalErrorKind, Object};
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use std::fmt;
+use std::rc::Rc;
+
+pub type BuiltinFunction = fn(Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError>;
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFunction,
+}
+
+// 関数ポインタの比較は当てにならないので名前で比較する
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Builtin {}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        func: len,
+    },
+    Builtin {
+        name: "puts",
+        func: puts,
+    },
+    Builtin {
+        name: "first",
+        func: first,
+    },
+    Builtin {
+        name: "last",
+        func: last,
+    },
+    Builtin {
+        name: "rest",
+        func: rest,
+    },
+    Builtin {
+        name: "push",
+        func: push,
+    },
+    Builtin {
+        name: "int",
+        func: int,
+    },
+    Builtin {
+        name: "float",
+        func: float,
+    },
+    Builtin {
+        name: "str",
+        func: str,
+    },
+    Builtin {
+        name: "range",
+        func: range,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<Rc<Object>> {
+    BUILTINS
+        .iter()
+        .find(|b| b.name == name)
+        .map(|b| Rc::new(Object::Builtin(*b)))
+}
+
+fn check_arity(arguments: &[Rc<Object>], want: usize) -> Result<(), EvalError> {
+    if arguments.len() != want {
+        return Err(EvalErrorKind::ArityMismatch {
+            expected: want,
+            got: arguments.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn len(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    match &*arguments[0] {
+        Object::String(s) => Ok(Rc::new(Object::Integer(s.chars().count() as i64))),
+        Object::Array(elements) => Ok(Rc::new(Object::Integer(elements.len() as i64))),
+        // 長さはi64に収まらないことがある
+        Object::Range { start, end } => Ok(Rc::new(Object::integer(
+            (BigInt::from(*end) - start).max(BigInt::from(0)),
+        ))),
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "len",
+            expected: "STRING, ARRAY or RANGE",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn puts(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    for argument in arguments {
+        println!("{}", argument);
+    }
+    Ok(Rc::new(Object::Null))
+}
+
+fn first(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    match &*arguments[0] {
+        Object::Array(elements) => Ok(elements
+            .first()
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Object::Null))),
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "first",
+            expected: "ARRAY",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn last(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    match &*arguments[0] {
+        Object::Array(elements) => Ok(elements
+            .last()
+            .cloned()
+            .unwrap_or_else(|| Rc::new(Object::Null))),
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "last",
+            expected: "ARRAY",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn rest(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    match &*arguments[0] {
+        Object::Array(elements) if elements.is_empty() => Ok(Rc::new(Object::Null)),
+        Object::Array(elements) => Ok(Rc::new(Object::Array(elements[1..].to_vec()))),
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "rest",
+            expected: "ARRAY",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn push(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 2)?;
+    match &*arguments[0] {
+        Object::Array(elements) => {
+            let mut elements = elements.clone();
+            elements.push(arguments[1].clone());
+            Ok(Rc::new(Object::Array(elements)))
+        }
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "push",
+            expected: "ARRAY",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn invalid_conversion(value: &Object, target: &'static str) -> EvalError {
+    let value = match value {
+        Object::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    };
+    EvalErrorKind::InvalidConversion { value, target }.into()
+}
+
+// 浮動小数点数は0の方向に切り捨てる
+fn int(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    let argument = &*arguments[0];
+    match argument {
+        Object::Integer(_) | Object::BigInteger(_) => Ok(arguments[0].clone()),
+        Object::Float(x) => match BigInt::from_f64(x.trunc()) {
+            Some(i) => Ok(Rc::new(Object::integer(i))),
+            None => Err(invalid_conversion(argument, "INTEGER")),
+        },
+        Object::String(s) => match s.trim().parse::<BigInt>() {
+            Ok(i) => Ok(Rc::new(Object::integer(i))),
+            Err(_) => Err(invalid_conversion(argument, "INTEGER")),
+        },
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "int",
+            expected: "INTEGER, FLOAT or STRING",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn float(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    let argument = &*arguments[0];
+    match argument {
+        Object::Integer(i) => Ok(Rc::new(Object::Float(*i as f64))),
+        Object::BigInteger(i) => Ok(Rc::new(Object::Float(big_to_float(i)))),
+        Object::Float(_) => Ok(arguments[0].clone()),
+        Object::String(s) => match s.trim().parse() {
+            Ok(x) => Ok(Rc::new(Object::Float(x))),
+            Err(_) => Err(invalid_conversion(argument, "FLOAT")),
+        },
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "float",
+            expected: "INTEGER, FLOAT or STRING",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn str(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    check_arity(&arguments, 1)?;
+    match &*arguments[0] {
+        Object::String(_) => Ok(arguments[0].clone()),
+        other => Ok(Rc::new(Object::String(Rc::new(other.to_string())))),
+    }
+}
+
+// range(end)は0から、range(start, end)はstartから数え、どちらもendを含まない
+fn range(arguments: Vec<Rc<Object>>) -> Result<Rc<Object>, EvalError> {
+    let (start, end) = match arguments.len() {
+        1 => (0, range_bound(&arguments[0])?),
+        2 => (range_bound(&arguments[0])?, range_bound(&arguments[1])?),
+        got => return Err(EvalErrorKind::ArityMismatch { expected: 2, got }.into()),
+    };
+    Ok(Rc::new(Object::Range { start, end }))
+}
+
+fn range_bound(argument: &Object) -> Result<i64, EvalError> {
+    match argument {
+        Object::Integer(i) => Ok(*i),
+        Object::BigInteger(i) => Err(EvalErrorKind::OutOfRange {
+            what: "range bound",
+            value: i.to_string(),
+        }
+        .into()),
+        other => Err(EvalErrorKind::InvalidArgument {
+            function: "range",
+            expected: "INTEGER",
+            got: other.type_name(),
+        }
+        .into()),
+    }
+}
@@ -4,22 +4,45 @@ use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Clone)]
 pub struct Environment {
     pub store: HashMap<String, Rc<Object>>,
     pub outer: Option<Rc<RefCell<Environment>>>,
 }
 
+// 再帰関数は自分を束縛している環境を持つので、中身まで辿ると循環してしまう。
+// そのため比較は同一性で行い、Debugでは名前だけを出す。
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Environment {}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.store.keys().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &self.outer.is_some())
+            .finish()
+    }
+}
+
 impl fmt::Display for Environment {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut s = String::from("{{");
-        for (i, (k, v)) in self.store.iter().enumerate() {
+        let mut entries: Vec<_> = self.store.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let mut s = String::from("{");
+        for (i, (k, v)) in entries.into_iter().enumerate() {
             if i != 0 {
                 s.push_str(", ");
             }
             s.push_str(&format!("{}: {}", k, v));
         }
-        s.push_str("}}");
+        s.push('}');
         write!(f, "{}", s)
     }
 }
@@ -38,4 +61,16 @@ impl Environment {
     pub fn set(&mut self, name: &str, val: Rc<Object>) -> Option<Rc<Object>> {
         self.store.insert(name.to_string(), val.clone())
     }
+
+    // 名前を定義している一番内側の環境で値を置き換える。どこにもなければfalseを返す
+    pub fn assign(&mut self, name: &str, val: Rc<Object>) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = val;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, val),
+            None => false,
+        }
+    }
 }
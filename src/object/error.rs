@@ -0,0 +1,181 @@
+use crate::ast::{Identifier, InfixOperator, PrefixOperator};
+use crate::span::{Files, Span};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    // エラーの発生した式の位置
+    pub span: Option<Span>,
+    // エラーが起きたときに実行中だった関数。内側の呼び出しが先頭に来る
+    pub trace: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalErrorKind {
+    TypeMismatch {
+        left: &'static str,
+        operator: InfixOperator,
+        right: &'static str,
+    },
+    UnknownPrefixOperator {
+        operator: PrefixOperator,
+        right: &'static str,
+    },
+    UnknownOperator {
+        left: &'static str,
+        operator: InfixOperator,
+        right: &'static str,
+    },
+    UnknownIdentifier(Identifier),
+    // letで定義されていない名前への代入
+    UndefinedAssignment(Identifier),
+    NotAFunction(&'static str),
+    IndexNotSupported {
+        left: &'static str,
+        index: &'static str,
+    },
+    UnusableAsHashKey(&'static str),
+    // for-inで繰り返せない値
+    NotIterable(&'static str),
+    ArityMismatch {
+        expected: usize,
+        got: usize,
+    },
+    InvalidArgument {
+        function: &'static str,
+        expected: &'static str,
+        got: &'static str,
+    },
+    StackOverflow,
+    DivisionByZero,
+    // 変換できない値。valueは表示用に整形したもの
+    InvalidConversion {
+        value: String,
+        target: &'static str,
+    },
+    // 大きすぎる指数など、演算できない範囲のオペランド
+    OutOfRange {
+        what: &'static str,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: Option<Identifier>,
+    pub call_site: Span,
+}
+
+impl EvalError {
+    pub fn new(kind: EvalErrorKind) -> Self {
+        EvalError {
+            kind,
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+
+    // 位置がまだ決まっていなければspanを発生位置とする
+    pub fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+
+impl From<EvalErrorKind> for EvalError {
+    fn from(kind: EvalErrorKind) -> Self {
+        EvalError::new(kind)
+    }
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErrorKind::TypeMismatch {
+                left,
+                operator,
+                right,
+            } => write!(f, "type mismatch: {} {} {}", left, operator, right),
+            EvalErrorKind::UnknownPrefixOperator { operator, right } => {
+                write!(f, "unknown operator: {}{}", operator, right)
+            }
+            EvalErrorKind::UnknownOperator {
+                left,
+                operator,
+                right,
+            } => write!(f, "unknown operator: {} {} {}", left, operator, right),
+            EvalErrorKind::UnknownIdentifier(name) => write!(f, "identifier not found: {}", name),
+            EvalErrorKind::UndefinedAssignment(name) => {
+                write!(f, "cannot assign to undefined variable: {}", name)
+            }
+            EvalErrorKind::NotAFunction(type_name) => write!(f, "not a function: {}", type_name),
+            EvalErrorKind::IndexNotSupported { left, index } => {
+                write!(f, "index operator not supported: {}[{}]", left, index)
+            }
+            EvalErrorKind::UnusableAsHashKey(type_name) => {
+                write!(f, "unusable as hash key: {}", type_name)
+            }
+            EvalErrorKind::NotIterable(type_name) => write!(f, "not iterable: {}", type_name),
+            EvalErrorKind::ArityMismatch { expected, got } => write!(
+                f,
+                "wrong number of arguments. got={}, want={}",
+                got, expected
+            ),
+            EvalErrorKind::InvalidArgument {
+                function,
+                expected,
+                got,
+            } => write!(
+                f,
+                "argument to `{}` must be {}, got {}",
+                function, expected, got
+            ),
+            EvalErrorKind::StackOverflow => write!(f, "stack overflow"),
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::InvalidConversion { value, target } => {
+                write!(f, "cannot convert {} to {}", value, target)
+            }
+            EvalErrorKind::OutOfRange { what, value } => {
+                write!(f, "{} out of range: {}", what, value)
+            }
+        }
+    }
+}
+
+impl EvalError {
+    // 各位置に、filesで分かるファイル名を付けて表示する
+    pub fn render(&self, files: &Files) -> String {
+        let mut output = match self.span {
+            Some(span) => format!("{}: {}", span.display(files), self.kind),
+            None => self.kind.to_string(),
+        };
+        // 再帰で同じ呼び出しが続くときは1行にまとめる
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            let name = frame
+                .function
+                .as_ref()
+                .map_or("<anonymous>", |n| n.as_str());
+            output.push_str(&format!(
+                "\n    in {} called at {}",
+                name,
+                frame.call_site.display(files)
+            ));
+            let mut repeated = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeated += 1;
+            }
+            if repeated > 0 {
+                output.push_str(&format!("\n    ... repeated {} more times", repeated));
+            }
+        }
+        output
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(&Files::default()))
+    }
+}
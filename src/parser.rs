@@ -1,14 +1,55 @@
 use crate::ast::{self, *};
 use crate::lexer::Lexer;
+use crate::span::{Files, Span};
 use crate::token::Token;
+mod test;
+use core::fmt;
 use core::panic;
 use std::vec;
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub expected: String,
+    pub found: Token,
+    pub span: Span,
+    // 期待と違うトークンではなく、書けない形の式を見つけたときはその説明
+    pub message: Option<String>,
+}
+
+impl ParseError {
+    // 位置に、filesで分かるファイル名を付けて表示する
+    pub fn render(&self, files: &Files) -> String {
+        let span = self.span.display(files);
+        if let Some(message) = &self.message {
+            return format!("{}: {}", span, message);
+        }
+        match &self.found {
+            // 字句解析のエラーは、何を期待していたかより原因のほうが役に立つ
+            Token::Error(message) => format!("{}: {}", span, message),
+            Token::EOF => format!("{}: expected {}, found end of input", span, self.expected),
+            found => format!("{}: expected {}, found {}", span, self.expected, found),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(&Files::default()))
+    }
+}
+
 #[derive(Debug)]
 pub struct Parser {
     lexer: Lexer,
     cur_token: Token,
     peek_token: Token,
+    cur_span: Span,
+    peek_span: Span,
+    errors: Vec<ParseError>,
+    // いま解析しているブロックの深さ。エラーからの復帰に使う
+    block_depth: usize,
+    // 関数の中で囲んでいるループの数。breakとcontinueはループの中にだけ書ける
+    loop_depth: usize,
 }
 
 impl Parser {
@@ -17,6 +58,11 @@ impl Parser {
             lexer,
             cur_token: Token::EOF,
             peek_token: Token::EOF,
+            cur_span: Span::default(),
+            peek_span: Span::default(),
+            errors: Vec::new(),
+            block_depth: 0,
+            loop_depth: 0,
         };
         parser.next_token();
         parser.next_token();
@@ -24,134 +70,215 @@ impl Parser {
     }
 
     pub fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        let next = self.lexer.next_token();
+        self.cur_token = std::mem::replace(&mut self.peek_token, next.token);
+        self.cur_span = std::mem::replace(&mut self.peek_span, next.span);
+    }
+
+    // startから現在のトークンまでを覆うノードを作る
+    fn statement_node(&self, kind: StatementKind, start: Span) -> Statement {
+        Statement {
+            kind,
+            span: start.to(self.cur_span),
+        }
+    }
+
+    fn expression_node(&self, kind: ExpressionKind, start: Span) -> Expression {
+        Expression {
+            kind,
+            span: start.to(self.cur_span),
+        }
+    }
+
+    fn cur_error(&self, expected: &str) -> ParseError {
+        ParseError {
+            expected: expected.to_string(),
+            found: self.cur_token.clone(),
+            span: self.cur_span,
+            message: None,
+        }
+    }
+
+    fn peek_error(&self, expected: &str) -> ParseError {
+        ParseError {
+            expected: expected.to_string(),
+            found: self.peek_token.clone(),
+            span: self.peek_span,
+            message: None,
+        }
+    }
+
+    // 次のトークンが期待通りなら読み進める
+    fn expect_peek(&mut self, token: Token) -> Result<(), ParseError> {
+        if self.peek_token != token {
+            return Err(self.peek_error(&format!("`{}`", token)));
+        }
+        self.next_token();
+        Ok(())
+    }
+
+    // エラーが起きた文を読み飛ばし、次の文の先頭(かブロックの終わり)まで進める
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        let mut first = true;
+        loop {
+            match self.cur_token {
+                Token::EOF => return,
+                // ブロックの終わりならそこで止まり、対応する`{`のない`}`なら読み飛ばす
+                Token::RBrace if depth == 0 => {
+                    if self.block_depth == 0 {
+                        self.next_token();
+                    }
+                    return;
+                }
+                Token::Let | Token::Return if depth == 0 && !first => return,
+                Token::Semicolon if depth == 0 => {
+                    self.next_token();
+                    return;
+                }
+                Token::LBrace => depth += 1,
+                Token::RBrace => depth -= 1,
+                _ => (),
+            }
+            first = false;
+            self.next_token();
+        }
     }
 
-    pub fn program(&mut self) -> ast::Program {
+    pub fn program(&mut self) -> (ast::Program, Vec<ParseError>) {
         let mut program = Vec::new();
         while self.cur_token != Token::EOF {
-            let stmt = self.statement();
-            if let Some(stmt) = stmt {
-                program.push(stmt);
+            match self.statement() {
+                Ok(stmt) => {
+                    program.push(stmt);
+                    self.next_token();
+                }
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
             }
-
-            self.next_token();
         }
-        program
+        (program, std::mem::take(&mut self.errors))
     }
 
-    fn statement(&mut self) -> Option<Statement> {
+    fn statement(&mut self) -> Result<Statement, ParseError> {
         match &self.cur_token {
             Token::Let => self.let_statement(),
             Token::Return => self.return_statement(),
+            Token::Break | Token::Continue => self.loop_control_statement(),
             _ => self.expression_statement(),
         }
     }
 
-    fn let_statement(&mut self) -> Option<Statement> {
+    fn let_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.cur_span;
         let name = match self.peek_token {
             Token::Ident(ref name) => name.clone(),
-            _ => return None,
+            _ => return Err(self.peek_error("identifier")),
         };
 
         self.next_token();
 
-        if self.peek_token != Token::Assign {
-            return None;
-        }
+        self.expect_peek(Token::Assign)?;
 
         self.next_token();
-        self.next_token();
-
-        let value = match self.expression(Precedence::Lowest) {
-            Some(value) => value,
-            None => return None,
-        };
 
-        // ```サンプルコードの実装
-        //if p.peekTokenIs(token.SEMICOLON) {
-        //		p.nextToken()
-        //	}
-        //````
-        if self.peek_token != Token::Semicolon {
-            return None;
+        let mut value = self.expression(Precedence::Lowest)?;
+        if let ExpressionKind::FunctionLiteral {
+            name: ref mut function_name,
+            ..
+        } = value.kind
+        {
+            *function_name = Some(name.clone());
         }
 
-        self.next_token();
+        // サンプルコードと同じくセミコロンは省略できる
+        if self.peek_token == Token::Semicolon {
+            self.next_token();
+        }
 
-        Some(Statement::LetStatement { name, value })
+        Ok(self.statement_node(StatementKind::LetStatement { name, value }, start))
     }
 
-    fn return_statement(&mut self) -> Option<Statement> {
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
         if self.cur_token != Token::Return {
             panic!("cur_token is not return");
         }
+        let start = self.cur_span;
 
         self.next_token();
 
-        let value = match self.expression(Precedence::Lowest) {
-            Some(value) => value,
-            None => return None,
-        };
+        let value = self.expression(Precedence::Lowest)?;
 
-        // ```サンプルコードの実装
-        //if p.peekTokenIs(token.SEMICOLON) {
-        //		p.nextToken()
-        //	}
-        //````
-        if self.peek_token != Token::Semicolon {
-            return None;
+        // サンプルコードと同じくセミコロンは省略できる
+        if self.peek_token == Token::Semicolon {
+            self.next_token();
         }
 
-        self.next_token();
-
-        Some(Statement::Return {
-            return_value: value,
-        })
+        Ok(self.statement_node(
+            StatementKind::Return {
+                return_value: value,
+            },
+            start,
+        ))
     }
 
-    fn expression_statement(&mut self) -> Option<Statement> {
-        let expression = match self.expression(Precedence::Lowest) {
-            Some(expression) => expression,
-            None => return None,
+    fn loop_control_statement(&mut self) -> Result<Statement, ParseError> {
+        let kind = match self.cur_token {
+            Token::Break => StatementKind::Break,
+            Token::Continue => StatementKind::Continue,
+            _ => panic!("cur_token is not break or continue"),
         };
+        if self.loop_depth == 0 {
+            return Err(self.cur_error(&format!("`{}` inside a loop", self.cur_token)));
+        }
+        let start = self.cur_span;
 
-        // ```サンプルコードの実装
-        //if p.peekTokenIs(token.SEMICOLON) {
-        //		p.nextToken()
-        //	}
-        //````
-        //　俺が書いたコード
-        // if self.peek_token != Token::Semicolon {
-        //     return None;
-        // }
+        if self.peek_token == Token::Semicolon {
+            self.next_token();
+        }
+
+        Ok(self.statement_node(kind, start))
+    }
 
-        // self.next_token();
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.cur_span;
+        let expression = self.expression(Precedence::Lowest)?;
 
         if self.peek_token == Token::Semicolon {
             self.next_token();
         }
 
-        Some(Statement::Expression { expression })
+        Ok(self.statement_node(StatementKind::Expression { expression }, start))
     }
 
-    fn expression(&mut self, precedence: Precedence) -> Option<Expression> {
+    fn expression(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
+        let start = self.cur_span;
         let mut left_exp = match &self.cur_token {
-            Token::Ident(name) => Some(Expression::Identifier(name.clone())),
-            Token::Int(i) => Some(Expression::IntegerLiteral(i.clone())),
-            Token::String(s) => Some(Expression::StringLiteral(s.clone())),
-            Token::Bang | Token::Minus => self.prefix_expression(),
-            Token::True => Some(Expression::Boolean(true)),
-            Token::False => Some(Expression::Boolean(false)),
-            Token::LParen => self.grouped_expression(),
-            Token::If => self.if_expression(),
-            Token::Function => self.function_literal(),
-            Token::LBracket => self.array_literal(),
-            Token::LBrace => self.hash_literal(),
-            _ => {
-                panic!("cur_token is not expression");
+            Token::Ident(name) => {
+                self.expression_node(ExpressionKind::Identifier(name.clone()), start)
+            }
+            Token::Int(i) => self.expression_node(ExpressionKind::IntegerLiteral(*i), start),
+            Token::BigInt(i) => {
+                self.expression_node(ExpressionKind::BigIntegerLiteral(i.clone()), start)
+            }
+            Token::Float(x) => self.expression_node(ExpressionKind::FloatLiteral(*x), start),
+            Token::String(s) => {
+                self.expression_node(ExpressionKind::StringLiteral(s.clone()), start)
             }
+            Token::TemplateHead(_) => self.template_string()?,
+            Token::True => self.expression_node(ExpressionKind::Boolean(true), start),
+            Token::False => self.expression_node(ExpressionKind::Boolean(false), start),
+            Token::Bang | Token::Minus | Token::Tilde => self.prefix_expression()?,
+            Token::LParen => self.grouped_expression()?,
+            Token::If => self.if_expression()?,
+            Token::While => self.while_expression()?,
+            Token::For => self.for_expression()?,
+            Token::Function => self.function_literal()?,
+            Token::LBracket => self.array_literal()?,
+            Token::LBrace => self.hash_literal()?,
+            _ => return Err(self.cur_error("expression")),
         };
 
         while self.peek_token != Token::Semicolon && precedence < self.peek_precedence() {
@@ -160,208 +287,389 @@ impl Parser {
                 | Token::Minus
                 | Token::Slash
                 | Token::Asterisk
+                | Token::Percent
+                | Token::Power
                 | Token::Eq
                 | Token::NotEq
                 | Token::Lt
-                | Token::Gt => {
+                | Token::Gt
+                | Token::LtEq
+                | Token::GtEq
+                | Token::And
+                | Token::Or
+                | Token::Ampersand
+                | Token::Pipe
+                | Token::Caret
+                | Token::ShiftLeft
+                | Token::ShiftRight => {
+                    self.next_token();
+                    left_exp = self.infix_expression(left_exp)?;
+                }
+                Token::Assign
+                | Token::PlusAssign
+                | Token::MinusAssign
+                | Token::AsteriskAssign
+                | Token::SlashAssign
+                | Token::PercentAssign
+                | Token::PowerAssign
+                | Token::AmpersandAssign
+                | Token::PipeAssign
+                | Token::CaretAssign
+                | Token::ShiftLeftAssign
+                | Token::ShiftRightAssign => {
                     self.next_token();
-                    left_exp = self.infix_expression(left_exp.expect("left_exp is None"));
+                    left_exp = self.assign_expression(left_exp)?;
                 }
                 Token::LParen => {
                     self.next_token();
-                    left_exp = self.call_expression(left_exp.expect("left_exp is None"));
+                    left_exp = self.call_expression(left_exp)?;
                 }
                 Token::LBracket => {
                     self.next_token();
-                    left_exp = self.index_expression(left_exp.expect("left_exp is None"));
+                    left_exp = self.index_expression(left_exp)?;
                 }
-                _ => return left_exp,
+                _ => return Ok(left_exp),
             };
         }
-        left_exp
+        Ok(left_exp)
     }
 
-    fn prefix_expression(&mut self) -> Option<Expression> {
+    fn prefix_expression(&mut self) -> Result<Expression, ParseError> {
         let operator = match self.cur_token {
             Token::Bang => PrefixOperator::Bang,
             Token::Minus => PrefixOperator::Minus,
+            Token::Tilde => PrefixOperator::BitNot,
             _ => panic!("cur_token is not prefix operator"),
         };
+        let start = self.cur_span;
 
         self.next_token();
 
-        let right = match self.expression(Precedence::Prefix) {
-            Some(right) => right,
-            None => return None,
-        };
+        let right = self.expression(Precedence::Prefix)?;
 
-        Some(Expression::PrefixExpression {
-            operator,
-            right: Box::new(right),
-        })
+        Ok(self.expression_node(
+            ExpressionKind::PrefixExpression {
+                operator,
+                right: Box::new(right),
+            },
+            start,
+        ))
     }
 
-    fn infix_expression(&mut self, left: Expression) -> Option<Expression> {
+    fn infix_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
         let operator = match self.cur_token {
             Token::Plus => InfixOperator::Plus,
             Token::Minus => InfixOperator::Minus,
             Token::Slash => InfixOperator::Slash,
             Token::Asterisk => InfixOperator::Asterisk,
+            Token::Percent => InfixOperator::Percent,
+            Token::Power => InfixOperator::Power,
             Token::Eq => InfixOperator::Eq,
             Token::NotEq => InfixOperator::NotEq,
             Token::Lt => InfixOperator::Lt,
             Token::Gt => InfixOperator::Gt,
+            Token::LtEq => InfixOperator::LtEq,
+            Token::GtEq => InfixOperator::GtEq,
+            Token::And => InfixOperator::And,
+            Token::Or => InfixOperator::Or,
+            Token::Ampersand => InfixOperator::BitAnd,
+            Token::Pipe => InfixOperator::BitOr,
+            Token::Caret => InfixOperator::BitXor,
+            Token::ShiftLeft => InfixOperator::ShiftLeft,
+            Token::ShiftRight => InfixOperator::ShiftRight,
             _ => panic!("cur_token is not infix operator"),
         };
-        let precedece = self.cur_precedence();
+        // `**`は右結合なので、右辺では同じ優先順位の演算子も続けて読む
+        let precedece = match operator {
+            InfixOperator::Power => Precedence::Prefix,
+            _ => self.cur_precedence(),
+        };
 
         self.next_token();
 
-        let right = match self.expression(precedece) {
-            Some(right) => right,
-            None => return None,
-        };
-
-        Some(Expression::InfixExpression {
-            left: Box::new(left),
-            operator,
-            right: Box::new(right),
-        })
+        let right = self.expression(precedece)?;
+
+        let start = left.span;
+        Ok(self.expression_node(
+            ExpressionKind::InfixExpression {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+            start,
+        ))
     }
 
-    fn grouped_expression(&mut self) -> Option<Expression> {
+    // 代入は右結合なので、`a = b = 1`は`a = (b = 1)`になる
+    fn assign_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
+        let operator = match self.cur_token {
+            Token::Assign => None,
+            Token::PlusAssign => Some(InfixOperator::Plus),
+            Token::MinusAssign => Some(InfixOperator::Minus),
+            Token::AsteriskAssign => Some(InfixOperator::Asterisk),
+            Token::SlashAssign => Some(InfixOperator::Slash),
+            Token::PercentAssign => Some(InfixOperator::Percent),
+            Token::PowerAssign => Some(InfixOperator::Power),
+            Token::AmpersandAssign => Some(InfixOperator::BitAnd),
+            Token::PipeAssign => Some(InfixOperator::BitOr),
+            Token::CaretAssign => Some(InfixOperator::BitXor),
+            Token::ShiftLeftAssign => Some(InfixOperator::ShiftLeft),
+            Token::ShiftRightAssign => Some(InfixOperator::ShiftRight),
+            _ => panic!("cur_token is not assignment operator"),
+        };
+        let name = match left.kind {
+            ExpressionKind::Identifier(name) => name,
+            _ => {
+                // 演算や添字の式は外側を括弧で囲んで表示されるので、それを外して見せる
+                let target = left.to_string();
+                let target = match left.kind {
+                    ExpressionKind::PrefixExpression { .. }
+                    | ExpressionKind::InfixExpression { .. }
+                    | ExpressionKind::IndexExpression { .. } => &target[1..target.len() - 1],
+                    _ => &target,
+                };
+                return Err(ParseError {
+                    expected: "variable".to_string(),
+                    found: self.cur_token.clone(),
+                    span: left.span,
+                    message: Some(format!("cannot assign to `{}`", target)),
+                });
+            }
+        };
+
         self.next_token();
 
-        let exp = self.expression(Precedence::Lowest);
+        let value = self.expression(Precedence::Lowest)?;
 
-        if self.peek_token != Token::RParen {
-            return None;
+        Ok(self.expression_node(
+            ExpressionKind::Assign {
+                name,
+                operator,
+                value: Box::new(value),
+            },
+            left.span,
+        ))
+    }
+
+    fn template_string(&mut self) -> Result<Expression, ParseError> {
+        let start = self.cur_span;
+        let mut parts = Vec::new();
+        let mut literal = match &self.cur_token {
+            Token::TemplateHead(s) => s.clone(),
+            _ => panic!("cur_token is not template head"),
+        };
+        loop {
+            // 空の部分は値に影響しないので省く
+            if !literal.is_empty() {
+                parts.push(TemplatePart::Literal(literal));
+            }
+            self.next_token();
+            parts.push(TemplatePart::Expression(
+                self.expression(Precedence::Lowest)?,
+            ));
+            self.next_token();
+            match &self.cur_token {
+                Token::TemplateMiddle(s) => literal = s.clone(),
+                Token::TemplateTail(s) => {
+                    if !s.is_empty() {
+                        parts.push(TemplatePart::Literal(s.clone()));
+                    }
+                    break;
+                }
+                _ => return Err(self.cur_error("`}`")),
+            }
         }
+        Ok(self.expression_node(ExpressionKind::TemplateString(parts), start))
+    }
 
+    fn grouped_expression(&mut self) -> Result<Expression, ParseError> {
         self.next_token();
 
-        return exp;
+        let exp = self.expression(Precedence::Lowest)?;
+
+        self.expect_peek(Token::RParen)?;
+
+        Ok(exp)
     }
 
-    fn if_expression(&mut self) -> Option<Expression> {
+    fn if_expression(&mut self) -> Result<Expression, ParseError> {
         if self.cur_token != Token::If {
             panic!("cur_token is not if");
         }
+        let start = self.cur_span;
 
-        if self.peek_token != Token::LParen {
-            return None;
-        }
+        self.expect_peek(Token::LParen)?;
 
-        self.next_token();
         self.next_token();
 
-        let condition = match self.expression(Precedence::Lowest) {
-            Some(condition) => condition,
-            None => return None,
-        };
+        let condition = self.expression(Precedence::Lowest)?;
 
-        if self.peek_token != Token::RParen {
-            return None;
+        self.expect_peek(Token::RParen)?;
+
+        self.expect_peek(Token::LBrace)?;
+
+        let consequence = self.block_statement()?;
+
+        let mut alternative = None;
+
+        if self.peek_token == Token::Else {
+            self.next_token();
+
+            self.expect_peek(Token::LBrace)?;
+
+            alternative = Some(self.block_statement()?);
         }
 
-        self.next_token();
+        Ok(self.expression_node(
+            ExpressionKind::IfExpression {
+                condition: Box::new(condition),
+                consequence,
+                alternative,
+            },
+            start,
+        ))
+    }
 
-        if self.peek_token != Token::LBrace {
-            return None;
+    fn while_expression(&mut self) -> Result<Expression, ParseError> {
+        if self.cur_token != Token::While {
+            panic!("cur_token is not while");
         }
+        let start = self.cur_span;
+
+        self.expect_peek(Token::LParen)?;
 
         self.next_token();
 
-        let consequence = match self.block_statement() {
-            Some(consequence) => consequence,
-            None => return None,
-        };
+        let condition = self.expression(Precedence::Lowest)?;
 
-        let mut alternative = None;
+        self.expect_peek(Token::RParen)?;
 
-        if self.peek_token == Token::Else {
-            self.next_token();
+        self.expect_peek(Token::LBrace)?;
 
-            if self.peek_token != Token::LBrace {
-                return None;
-            }
+        let body = self.loop_body()?;
 
-            self.next_token();
+        Ok(self.expression_node(
+            ExpressionKind::While {
+                condition: Box::new(condition),
+                body,
+            },
+            start,
+        ))
+    }
 
-            // retun noneをは挟むのはpaseができないとnoneを返すことにしているから
-            alternative = Some(match self.block_statement() {
-                Some(alternative) => alternative,
-                None => return None,
-            });
+    fn for_expression(&mut self) -> Result<Expression, ParseError> {
+        if self.cur_token != Token::For {
+            panic!("cur_token is not for");
         }
+        let start = self.cur_span;
+
+        self.expect_peek(Token::LParen)?;
+
+        let variable = match self.peek_token {
+            Token::Ident(ref name) => name.clone(),
+            _ => return Err(self.peek_error("identifier")),
+        };
+        self.next_token();
+
+        self.expect_peek(Token::In)?;
+
+        self.next_token();
+
+        let iterable = self.expression(Precedence::Lowest)?;
+
+        self.expect_peek(Token::RParen)?;
 
-        Some(Expression::IfExpression {
-            condition: Box::new(condition),
-            consequence: consequence,
-            alternative: alternative,
-        })
+        self.expect_peek(Token::LBrace)?;
+
+        let body = self.loop_body()?;
+
+        Ok(self.expression_node(
+            ExpressionKind::For {
+                variable,
+                iterable: Box::new(iterable),
+                body,
+            },
+            start,
+        ))
     }
 
-    fn block_statement(&mut self) -> Option<BlockStatement> {
+    fn loop_body(&mut self) -> Result<BlockStatement, ParseError> {
+        self.loop_depth += 1;
+        let body = self.block_statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    // ブロック内の文のエラーはここで記録して読み飛ばすので、
+    // 1つの誤りでブロック全体が捨てられることはない
+    fn block_statement(&mut self) -> Result<BlockStatement, ParseError> {
         let mut statements = Vec::new();
 
+        self.block_depth += 1;
         self.next_token();
 
         while self.cur_token != Token::RBrace && self.cur_token != Token::EOF {
             match self.statement() {
-                Some(stmt) => statements.push(stmt),
-                None => return None,
+                Ok(stmt) => {
+                    statements.push(stmt);
+                    self.next_token();
+                }
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
             }
+        }
+        self.block_depth -= 1;
 
-            self.next_token();
+        if self.cur_token != Token::RBrace {
+            return Err(self.cur_error("`}`"));
         }
 
-        Some(statements)
+        Ok(statements)
     }
 
-    fn function_literal(&mut self) -> Option<Expression> {
+    fn function_literal(&mut self) -> Result<Expression, ParseError> {
         if self.cur_token != Token::Function {
             panic!("cur_token is not function");
         }
+        let start = self.cur_span;
 
-        if self.peek_token != Token::LParen {
-            return None;
-        }
-
-        self.next_token();
+        self.expect_peek(Token::LParen)?;
 
-        let parameters = match self.function_parameters() {
-            Some(parameters) => parameters,
-            None => return None,
-        };
-
-        if self.peek_token != Token::LBrace {
-            return None;
-        }
+        let parameters = self.function_parameters()?;
 
-        self.next_token();
+        self.expect_peek(Token::LBrace)?;
 
-        let body = match self.block_statement() {
-            Some(body) => body,
-            None => return None,
-        };
+        // 関数の中から外側のループは抜けられない
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.block_statement();
+        self.loop_depth = loop_depth;
+        let body = body?;
 
-        Some(Expression::FunctionLiteral { parameters, body })
+        Ok(self.expression_node(
+            ExpressionKind::FunctionLiteral {
+                name: None,
+                parameters,
+                body,
+            },
+            start,
+        ))
     }
 
-    fn function_parameters(&mut self) -> Option<Vec<Identifier>> {
+    fn function_parameters(&mut self) -> Result<Vec<Identifier>, ParseError> {
         let mut identifiers = Vec::new();
 
         if self.peek_token == Token::RParen {
             self.next_token();
-            return Some(identifiers);
+            return Ok(identifiers);
         }
 
         self.next_token();
 
         match self.cur_token {
             Token::Ident(ref name) => identifiers.push(name.clone()),
-            _ => return None,
+            _ => return Err(self.cur_error("parameter name")),
         }
 
         while self.peek_token == Token::Comma {
@@ -370,32 +678,29 @@ impl Parser {
 
             match self.cur_token {
                 Token::Ident(ref name) => identifiers.push(name.clone()),
-                _ => return None,
+                _ => return Err(self.cur_error("parameter name")),
             }
         }
 
-        if self.peek_token != Token::RParen {
-            return None;
-        }
-
-        self.next_token();
+        self.expect_peek(Token::RParen)?;
 
-        Some(identifiers)
+        Ok(identifiers)
     }
 
-    fn call_expression(&mut self, function: Expression) -> Option<Expression> {
-        let arguments = match self.expression_list(Token::RParen) {
-            Some(arguments) => arguments,
-            None => return None,
-        };
-
-        Some(Expression::CallExpression {
-            function: Box::new(function),
-            arguments,
-        })
+    fn call_expression(&mut self, function: Expression) -> Result<Expression, ParseError> {
+        let arguments = self.expression_list(Token::RParen)?;
+
+        let start = function.span;
+        Ok(self.expression_node(
+            ExpressionKind::CallExpression {
+                function: Box::new(function),
+                arguments,
+            },
+            start,
+        ))
     }
 
-    fn expression_list(&mut self, end: Token) -> Option<Vec<Expression>> {
+    fn expression_list(&mut self, end: Token) -> Result<Vec<Expression>, ParseError> {
         match &end {
             Token::RBrace | Token::RParen | Token::RBracket => (),
             _ => panic!("end is not ) or ] or }}. end is {:?}", end),
@@ -405,102 +710,78 @@ impl Parser {
 
         if self.peek_token == end {
             self.next_token();
-            return Some(arguments);
+            return Ok(arguments);
         }
 
         self.next_token();
 
-        match self.expression(Precedence::Lowest) {
-            Some(argument) => arguments.push(argument),
-            None => return None,
-        }
+        arguments.push(self.expression(Precedence::Lowest)?);
 
         while self.peek_token == Token::Comma {
             self.next_token();
             self.next_token();
 
-            match self.expression(Precedence::Lowest) {
-                Some(argument) => arguments.push(argument),
-                None => return None,
-            }
-        }
-
-        //ここも
-        if self.peek_token != end {
-            return None;
+            arguments.push(self.expression(Precedence::Lowest)?);
         }
 
-        self.next_token();
+        self.expect_peek(end)?;
 
-        Some(arguments)
+        Ok(arguments)
     }
 
-    fn array_literal(&mut self) -> Option<Expression> {
+    fn array_literal(&mut self) -> Result<Expression, ParseError> {
         if self.cur_token != Token::LBracket {
             panic!("cur_token is not [");
         }
+        let start = self.cur_span;
 
-        let elements = match self.expression_list(Token::RBracket) {
-            Some(elements) => elements,
-            None => return None,
-        };
+        let elements = self.expression_list(Token::RBracket)?;
 
-        Some(Expression::ArrayLiteral(elements))
+        Ok(self.expression_node(ExpressionKind::ArrayLiteral(elements), start))
     }
 
-    fn index_expression(&mut self, left: Expression) -> Option<Expression> {
+    fn index_expression(&mut self, left: Expression) -> Result<Expression, ParseError> {
         if self.cur_token != Token::LBracket {
             panic!("cur_token is not [");
         }
 
         self.next_token();
 
-        let index = match self.expression(Precedence::Lowest) {
-            Some(index) => index,
-            None => return None,
-        };
+        let index = self.expression(Precedence::Lowest)?;
 
-        if self.peek_token != Token::RBracket {
-            return None;
-        }
+        self.expect_peek(Token::RBracket)?;
 
-        self.next_token();
-
-        Some(Expression::IndexExpression {
-            left: Box::new(left),
-            index: Box::new(index),
-        })
+        let start = left.span;
+        Ok(self.expression_node(
+            ExpressionKind::IndexExpression {
+                left: Box::new(left),
+                index: Box::new(index),
+            },
+            start,
+        ))
     }
 
-    fn hash_literal(&mut self) -> Option<Expression> {
+    fn hash_literal(&mut self) -> Result<Expression, ParseError> {
         if self.cur_token != Token::LBrace {
             panic!("cur_token is not {{");
         }
+        let start = self.cur_span;
 
         let mut hash = vec![];
 
         while self.peek_token != Token::RBrace {
             self.next_token();
-            let key = match self.expression(Precedence::Lowest) {
-                Some(key) => key,
-                None => return None,
-            };
+            let key = self.expression(Precedence::Lowest)?;
 
-            if self.peek_token != Token::Colon {
-                return None;
-            }
-            self.next_token();
+            self.expect_peek(Token::Colon)?;
             self.next_token();
 
-            let value = match self.expression(Precedence::Lowest) {
-                Some(value) => value,
-                None => return None,
-            };
+            let value = self.expression(Precedence::Lowest)?;
 
             hash.push(HashPair { key, value });
 
             if self.peek_token != Token::RBrace && self.peek_token != Token::Comma {
-                return None;
+                return Err(self.peek_error("`,` or `}`"));
             }
 
             if self.peek_token == Token::Comma {
@@ -508,21 +789,36 @@ impl Parser {
             }
         }
 
-        if self.peek_token != Token::RBrace {
-            return None;
-        }
-
-        self.next_token();
+        self.expect_peek(Token::RBrace)?;
 
-        Some(Expression::HashLiteral(hash))
+        Ok(self.expression_node(ExpressionKind::HashLiteral(hash), start))
     }
 
     fn token_precedence(token: &Token) -> Precedence {
         match token {
+            Token::Assign
+            | Token::PlusAssign
+            | Token::MinusAssign
+            | Token::AsteriskAssign
+            | Token::SlashAssign
+            | Token::PercentAssign
+            | Token::PowerAssign
+            | Token::AmpersandAssign
+            | Token::PipeAssign
+            | Token::CaretAssign
+            | Token::ShiftLeftAssign
+            | Token::ShiftRightAssign => Precedence::Assign,
+            Token::Or => Precedence::LogicalOr,
+            Token::And => Precedence::LogicalAnd,
+            Token::Pipe => Precedence::BitOr,
+            Token::Caret => Precedence::BitXor,
+            Token::Ampersand => Precedence::BitAnd,
             Token::Eq | Token::NotEq => Precedence::Equals,
-            Token::Lt | Token::Gt => Precedence::LessGreater,
+            Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => Precedence::LessGreater,
+            Token::ShiftLeft | Token::ShiftRight => Precedence::Shift,
             Token::Plus | Token::Minus => Precedence::Sum,
-            Token::Slash | Token::Asterisk => Precedence::Product,
+            Token::Slash | Token::Asterisk | Token::Percent => Precedence::Product,
+            Token::Power => Precedence::Power,
             Token::LParen => Precedence::Call,
             Token::LBracket => Precedence::Index,
             _ => Precedence::Lowest,
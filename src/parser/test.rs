@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::{ParseError, Parser};
+    use crate::span::Files;
+
+    fn parse(input: &str) -> (Vec<String>, Vec<ParseError>) {
+        let mut parser = Parser::new(Lexer::new(input));
+        let (program, errors) = parser.program();
+        (program.iter().map(|s| s.to_string()).collect(), errors)
+    }
+
+    fn error_messages(input: &str) -> Vec<String> {
+        parse(input).1.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let tests = vec![
+            ("-a * b", "((-a) * b)"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("a * [1, 2][b * c]", "(a * ([ 1 2][(b * c)]))"),
+            ("a <= b == b >= a", "((a <= b) == (b >= a))"),
+            ("a + b % c", "(a + (b % c))"),
+            ("-a ** b ** c * d", "((-(a ** (b ** c))) * d)"),
+            ("a || b && c == d", "(a || (b && (c == d)))"),
+            ("a | b ^ c & d == e", "(a | (b ^ (c & (d == e))))"),
+            ("a << b + c < d >> e", "((a << (b + c)) < (d >> e))"),
+            ("~a & ~-b", "((~a) & (~(-b)))"),
+            ("a & b && c | d", "((a & b) && (c | d))"),
+            (r#""a${b + c}d${e}""#, "a${(b + c)}d${e}"),
+            ("a = b = c || d", "(a = (b = (c || d)))"),
+            ("x += y * 2", "(x += (y * 2))"),
+            ("n <<= 1 + f(m -= 1)", "(n <<= (1 + f( (m -= 1))))"),
+            ("while (i < n) { i += 1 }", "while (i < n) (i += 1)"),
+            (
+                "for (x in [1, 2]) { if (x) { break } continue; }",
+                "for (x in [ 1 2]) if x break;, continue;",
+            ),
+        ];
+        for (input, expected) in tests {
+            let (program, errors) = parse(input);
+            assert!(errors.is_empty(), "input: {}, errors: {:?}", input, errors);
+            assert_eq!(program, vec![expected.to_string()], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_optional_semicolons() {
+        let (program, errors) = parse("let x = 5\nreturn x\nx");
+        assert!(errors.is_empty());
+        assert_eq!(program, vec!["let x = 5;", "return x;", "x"]);
+
+        let (program, errors) = parse("fn(x) { return x }");
+        assert!(errors.is_empty());
+        assert_eq!(program, vec!["fn(x) return x;"]);
+    }
+
+    #[test]
+    fn test_unexpected_token_is_reported() {
+        assert_eq!(
+            error_messages(")"),
+            vec!["1:1: expected expression, found )"]
+        );
+        assert_eq!(
+            error_messages("let = 5;"),
+            vec!["1:5: expected identifier, found ="]
+        );
+        assert_eq!(
+            error_messages("if (x { 1 }"),
+            vec!["1:7: expected `)`, found {"]
+        );
+        assert_eq!(
+            error_messages("add(1, 2"),
+            vec!["1:9: expected `)`, found end of input"]
+        );
+        assert_eq!(
+            error_messages("fn(x) { x"),
+            vec!["1:10: expected `}`, found end of input"]
+        );
+        assert_eq!(
+            error_messages("a + b = 1; a[0] += 1"),
+            vec![
+                "1:1: cannot assign to `a + b`",
+                "1:12: cannot assign to `a[0]`"
+            ]
+        );
+        assert_eq!(
+            error_messages(r#"let s = "${}"; let t = "${1 2}";"#),
+            vec![
+                "1:12: expected expression, found `}` of template string",
+                "1:29: expected `}`, found Int(2)"
+            ]
+        );
+
+        // ファイルの番号が分かれば、位置の前にファイル名を付ける
+        let mut files = Files::default();
+        let file = files.add("script.mk");
+        let (_, errors) = Parser::new(Lexer::new("let x 1").with_file(file)).program();
+        assert_eq!(
+            errors[0].render(&files),
+            "script.mk:1:7: expected `=`, found Int(1)"
+        );
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        assert_eq!(
+            error_messages("break; if (true) { continue }"),
+            vec![
+                "1:1: expected `break` inside a loop, found break",
+                "1:20: expected `continue` inside a loop, found continue"
+            ]
+        );
+        // 関数の本体からは外側のループを抜けられない
+        assert_eq!(
+            error_messages("while (true) { fn() { break } }"),
+            vec!["1:23: expected `break` inside a loop, found break"]
+        );
+        assert_eq!(
+            error_messages("for (1 in x) { }"),
+            vec!["1:6: expected identifier, found Int(1)"]
+        );
+    }
+
+    #[test]
+    fn test_lexer_errors_are_reported() {
+        assert_eq!(
+            error_messages("let s = \"abc"),
+            vec!["1:9: unterminated string"]
+        );
+        assert_eq!(
+            error_messages("let s = \"\\q\"; let t = 1;\nu + /* x"),
+            vec![
+                "1:9: invalid escape sequence: \\q",
+                "2:5: unterminated block comment"
+            ]
+        );
+        assert_eq!(
+            error_messages("let x = 0xZZ + 1;"),
+            vec!["1:9: invalid digit `Z` in hexadecimal literal `0xZZ`"]
+        );
+    }
+
+    #[test]
+    fn test_recovers_at_statement_boundaries() {
+        let (program, errors) = parse("let x 5; let y = 10; let = 1; y");
+        assert_eq!(program, vec!["let y = 10;", "y"]);
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec![
+                "1:7: expected `=`, found Int(5)",
+                "1:26: expected identifier, found =",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovers_inside_blocks() {
+        let (program, errors) = parse("let f = fn() { let = 1; 2 }; f()");
+        assert_eq!(program, vec!["let f = fn() 2;", "f()"]);
+        assert_eq!(errors.len(), 1);
+
+        let (program, errors) = parse("} 1");
+        assert_eq!(program, vec!["1"]);
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -1,34 +1,73 @@
-use crate::evaluator;
+use crate::ast::{ExpressionKind, StatementKind};
+use crate::compiler::Compiler;
 use crate::lexer::Lexer;
-use crate::object::environment::Environment;
-use crate::parser::Parser;
-use crate::token::Token;
-use std::{
-    collections::HashMap,
-    io::{self, Write},
-};
-
-#[allow(dead_code)]
+use crate::object::Object;
+use crate::parser::{ParseError, Parser};
+use crate::runtime::{Backend, Runtime};
+use crate::span::{FileId, Files, Span};
+use crate::token::{SpannedToken, Token};
+use rustyline::{error::ReadlineError, DefaultEditor};
+mod test;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::{fs, io, str::FromStr};
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ReplMode {
     Lexre,
     Parser,
     Eval,
 }
 
-pub fn start(reple_mode: ReplMode) {
-    let mut env = Environment {
-        store: HashMap::new(),
-        outer: None,
+impl FromStr for ReplMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lexer" => Ok(ReplMode::Lexre),
+            "parser" => Ok(ReplMode::Parser),
+            "eval" => Ok(ReplMode::Eval),
+            _ => Err(format!(
+                "unknown mode: {} (expected eval, parser or lexer)",
+                s
+            )),
+        }
+    }
+}
+
+pub fn start(reple_mode: ReplMode, backend: Backend) {
+    // usernameを表示
+    let username = whoami::username();
+    println!(
+        "Hello {}! This is the Monkey programming language!",
+        username
+    );
+    let mut session = Session::new(backend);
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("cannot start the REPL: {}", e);
+            return;
+        }
     };
     // ループ
     loop {
-        // プロンプトを表示
-        print!(">> ");
-        // バッファをフラッシュ
-        io::stdout().flush().unwrap();
-        // 入力を受け取る
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        let input = match read_input(&mut editor) {
+            Some(input) => input,
+            None => return,
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.trim_end());
+
+        if let Some(command) = input.trim().strip_prefix(':') {
+            run_command(command, &mut session);
+            continue;
+        }
 
         match reple_mode {
             ReplMode::Lexre => {
@@ -38,39 +77,304 @@ pub fn start(reple_mode: ReplMode) {
                 print_ast(&input);
             }
             ReplMode::Eval => {
-                print_eval(&input, &mut env);
+                session.eval(&input);
+            }
+        }
+    }
+}
+
+const COMMANDS_HELP: &str = "\
+:env              show the variables defined in this session
+:reset            forget all variables
+:load <file>      evaluate a file into this session
+:save <file>      write this session's let definitions and assignments to a file
+:ast <code>       show how <code> is parsed
+:tokens <code>    show the tokens of <code>
+:bytecode <code>  show the bytecode <code> compiles to
+:help             show this help";
+
+// REPLで評価した環境と、`:save`のために成功したletと一番外側の代入の元のソースを保持する。
+// 関数やループの中での代入は、その文全体を再実行しないと再現できないので保存しない
+pub struct Session {
+    backend: Backend,
+    runtime: Runtime,
+    definitions: Vec<String>,
+    // `:load`で読み込んだファイルの名前。エラーの位置の表示に使う
+    files: Files,
+}
+
+impl Session {
+    pub fn new(backend: Backend) -> Self {
+        Session {
+            backend,
+            runtime: Runtime::new(backend),
+            definitions: Vec::new(),
+            files: Files::default(),
+        }
+    }
+
+    pub fn eval(&mut self, input: &str) {
+        self.eval_file(input, FileId::default());
+    }
+
+    // fileはエラーの位置に表示するファイル
+    fn eval_file(&mut self, input: &str, file: FileId) {
+        let lexer = Lexer::new(input).with_file(file);
+        let mut parser = Parser::new(lexer);
+        let (program, errors) = parser.program();
+        if !errors.is_empty() {
+            print_parse_errors(&errors, &self.files);
+            return;
+        }
+
+        let definitions: Vec<(Span, String)> = program
+            .iter()
+            .filter(|s| match &s.kind {
+                StatementKind::LetStatement { .. } => true,
+                StatementKind::Expression { expression } => {
+                    matches!(expression.kind, ExpressionKind::Assign { .. })
+                }
+                _ => false,
+            })
+            .map(|s| {
+                let source = input[s.span.start as usize..s.span.end as usize].to_string();
+                (s.span, source)
+            })
+            .collect();
+
+        match self.runtime.run(program) {
+            Ok(obj) => {
+                self.definitions
+                    .extend(definitions.into_iter().map(|(_, source)| source));
+                println!("{}", obj)
+            }
+            Err(e) => {
+                // 失敗した文より前のletは実行済みで、変数は環境に残っている。
+                // 失敗した文は、一番外側の呼び出し元かエラーの位置で分かる
+                if let Some(failed) = e.trace.last().map(|frame| frame.call_site).or(e.span) {
+                    self.definitions.extend(
+                        definitions
+                            .into_iter()
+                            .take_while(|(span, _)| span.end <= failed.start)
+                            .map(|(_, source)| source),
+                    );
+                }
+                println!("{}", e.render(&self.files))
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Session::new(self.backend);
+    }
+
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        let input = fs::read_to_string(path)?;
+        let file = self.files.add(path);
+        self.eval_file(&input, file);
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut output = String::new();
+        for definition in &self.definitions {
+            output.push_str(definition);
+            output.push('\n');
+        }
+        fs::write(path, output)
+    }
+}
+
+fn run_command(command: &str, session: &mut Session) {
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+    match (name, argument) {
+        ("env", "") => println!("{}", session.runtime),
+        ("reset", "") => session.reset(),
+        ("load", path) if !path.is_empty() => {
+            if let Err(e) = session.load(path) {
+                println!("cannot load {}: {}", path, e);
+            }
+        }
+        ("save", path) if !path.is_empty() => match session.save(path) {
+            Ok(()) => println!(
+                "saved {} definitions to {}",
+                session.definitions.len(),
+                path
+            ),
+            Err(e) => println!("cannot save {}: {}", path, e),
+        },
+        ("ast", code) => print_ast(code),
+        ("tokens", code) => print_token(code),
+        ("bytecode", code) => print_bytecode(code),
+        ("help", "") => println!("{}", COMMANDS_HELP),
+        _ => println!("unknown command: :{}\n{}", command, COMMANDS_HELP),
+    }
+}
+
+// 括弧や文字列が閉じるまで続きの行を読む。
+// Ctrl-Cは入力中の内容だけを捨て、Ctrl-D(EOF)ではNoneを返す。
+fn read_input(editor: &mut DefaultEditor) -> Option<String> {
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if !is_incomplete(&input) {
+                    return Some(input);
+                }
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => return None,
+            Err(e) => {
+                eprintln!("{}", e);
+                return None;
+            }
+        }
+    }
+}
+
+// 開いたままの括弧か、閉じていない文字列やブロックコメントがあればtrue
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut comment_depth = 0;
+    let mut in_string = false;
+    // 文字列に埋め込んだ式ごとの、`${`の外側の括弧の深さ
+    let mut interpolations = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut prev = ' ';
+    while let Some(ch) = chars.next() {
+        match ch {
+            '/' if !in_string && chars.next_if_eq(&'*').is_some() => comment_depth += 1,
+            '*' if comment_depth > 0 && chars.next_if_eq(&'/').is_some() => comment_depth -= 1,
+            _ if comment_depth > 0 => (),
+            // `"""`の文字列と生文字列には式を埋め込めないので、閉じるところまで読み飛ばす
+            '"' if !in_string && chars.clone().take(2).eq("\"\"".chars()) => {
+                chars.nth(1);
+                if !skip_past(&mut chars, "\"\"\"", true) {
+                    return true;
+                }
+            }
+            'r' if !in_string
+                && !prev.is_alphanumeric()
+                && prev != '_'
+                && matches!(chars.peek(), Some('"' | '#')) =>
+            {
+                let mut closing = String::from("\"");
+                while chars.next_if_eq(&'#').is_some() {
+                    closing.push('#');
+                }
+                if chars.next_if_eq(&'"').is_some() && !skip_past(&mut chars, &closing, false) {
+                    return true;
+                }
+            }
+            '\\' if in_string => {
+                chars.next();
             }
+            '$' if in_string && chars.next_if_eq(&'{').is_some() => {
+                interpolations.push(depth);
+                depth += 1;
+                in_string = false;
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => (),
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if ch == '}' && interpolations.last() == Some(&depth) {
+                    interpolations.pop();
+                    in_string = true;
+                }
+            }
+            _ => (),
         }
+        prev = ch;
     }
+    in_string || comment_depth > 0 || depth > 0
 }
 
-fn print_token(input: &String) {
-    let mut lexer = Lexer::new(input);
+// closingの直後まで読み進める。見つからなければfalse
+fn skip_past(chars: &mut Peekable<Chars>, closing: &str, escapes: bool) -> bool {
     loop {
-        let token = lexer.next_token();
-        println!("{:?}", token);
+        if chars.clone().take(closing.len()).eq(closing.chars()) {
+            chars.nth(closing.len() - 1);
+            return true;
+        }
+        match chars.next() {
+            Some('\\') if escapes => {
+                chars.next();
+            }
+            Some(_) => (),
+            None => return false,
+        }
+    }
+}
+
+pub fn print_token(input: &str) {
+    print_tokens(Lexer::new(input));
+}
+
+// トークンを読んだそばから表示するので、大きな入力も全体を読み込まずに済む
+pub fn print_tokens(lexer: Lexer) {
+    let mut lexer = lexer.with_trivia();
+    loop {
+        let SpannedToken { token, span } = lexer.next_token();
+        println!("{} {:?}", span, token);
         if token == Token::EOF {
             return;
         }
     }
 }
 
-fn print_ast(input: &String) {
+fn print_ast(input: &str) {
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let program = parser.program();
+    let (program, errors) = parser.program();
 
-    println!("{:?}", program);
+    for statement in &program {
+        println!("{}", statement);
+    }
+    print_parse_errors(&errors, &Files::default());
 }
 
-fn print_eval(input: &String, env: &mut Environment) {
+// メインの命令列と、定数表にある関数の命令列を出力する
+fn print_bytecode(input: &str) {
     let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
-    let program = parser.program();
+    let (program, errors) = parser.program();
+    if !errors.is_empty() {
+        print_parse_errors(&errors, &Files::default());
+        return;
+    }
+
+    let mut compiler = Compiler::new();
+    let main = compiler.compile(&program);
+    print!("{}", main.instructions);
+    for (i, constant) in compiler.constants().iter().enumerate() {
+        match &**constant {
+            Object::CompiledFunction(function) => {
+                let name = function.name.as_ref().map_or("<anonymous>", |n| n.as_str());
+                println!("\nconstant {}: fn {}", i, name);
+                print!("{}", function.instructions);
+            }
+            other => println!("\nconstant {}: {}", i, other),
+        }
+    }
+}
 
-    let result = evaluator::eval(crate::ast::Node::Program(program), env);
-    match result {
-        Ok(obj) => println!("{}", obj),
-        Err(e) => println!("{}", e),
+fn print_parse_errors(errors: &[ParseError], files: &Files) {
+    for error in errors {
+        println!("parse error: {}", error.render(files));
     }
 }
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use crate::repl::{is_incomplete, Session};
+    use crate::runtime::Backend;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("let x = 1;\n"));
+        assert!(is_incomplete("let f = fn(x) {\n"));
+        assert!(is_incomplete("let f = fn(x) {\n  if (x) {\n    1\n  }\n"));
+        assert!(!is_incomplete("let f = fn(x) {\n  x\n};\n"));
+        assert!(is_incomplete("add(1,\n"));
+        assert!(is_incomplete("[1, 2,\n"));
+        assert!(is_incomplete("\"hello\n"));
+        assert!(!is_incomplete("\"{\"\n"));
+        assert!(!is_incomplete("}\n"));
+        assert!(!is_incomplete("let a = 1; // (\n"));
+        assert!(is_incomplete("/* a /* b */\n"));
+        assert!(!is_incomplete("/* a /* b */ */ 1\n"));
+        assert!(!is_incomplete("\"/*\"\n"));
+        assert!(!is_incomplete("10 / 2\n"));
+        assert!(!is_incomplete("\"say \\\"hi\\\"\"\n"));
+        assert!(is_incomplete("\"ends with \\\"\n"));
+        assert!(!is_incomplete("\"${ {\"a\": 1}[\"a\"] }\"\n"));
+        assert!(is_incomplete("\"${ f(\n"));
+        assert!(is_incomplete("\"${x} (\n"));
+        assert!(!is_incomplete("\"$ { \"\n"));
+        assert!(is_incomplete("let q = \"\"\"\n  a \"b\n"));
+        assert!(!is_incomplete("let q = \"\"\"\n  a \"b ${\n\"\"\"\n"));
+        assert!(!is_incomplete("r\"C:\\\" + r#\"(\"#\n"));
+        assert!(is_incomplete("r#\"a\"\n"));
+        assert!(!is_incomplete("bar\"x\"\n"));
+    }
+
+    #[test]
+    fn test_session_records_successful_definitions() {
+        let mut session = Session::new(Backend::Evaluator);
+        session.eval("let a = 1; let f = fn(x) {\n  x + a\n}\nf(1)");
+        session.eval("let b = a + true;");
+        session.eval("let = 1;");
+        session.eval("let a = 2");
+        assert_eq!(
+            session.definitions,
+            vec!["let a = 1;", "let f = fn(x) {\n  x + a\n}", "let a = 2"]
+        );
+
+        // 一番外側の代入も、読み込み直したときに同じ値になるよう残す
+        let mut session = Session::new(Backend::Evaluator);
+        session.eval("let x = 1; x = 2; x += 3; let f = fn() { x = 10 }; f(); x");
+        assert_eq!(
+            session.definitions,
+            vec![
+                "let x = 1;",
+                "x = 2;",
+                "x += 3;",
+                "let f = fn() { x = 10 };"
+            ]
+        );
+
+        session.reset();
+        assert!(session.definitions.is_empty());
+        assert_eq!(session.runtime.to_string(), "{}");
+
+        // 後の文が失敗しても、それより前に実行したletは残す
+        for backend in [Backend::Evaluator, Backend::Vm] {
+            let mut session = Session::new(backend);
+            session.eval("let a = 1; let b = a + true; let c = 3;");
+            session.eval("let f = fn() { 1 + true };\nlet g = 2;\nf(); let h = 4;");
+            assert_eq!(
+                session.definitions,
+                vec!["let a = 1;", "let f = fn() { 1 + true };", "let g = 2;"],
+                "backend: {:?}",
+                backend
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::ast::{self, Node};
+use crate::compiler::Compiler;
+use crate::evaluator;
+use crate::object::environment::Environment;
+use crate::object::{EvalError, Object};
+use crate::vm::{Bytecode, Vm};
+use std::{cell::RefCell, fmt, rc::Rc, str::FromStr};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    // ASTをそのまま辿る評価器
+    Evaluator,
+    // バイトコードにコンパイルしてVMで実行する
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "evaluator" => Ok(Backend::Evaluator),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("unknown backend: {} (expected evaluator or vm)", s)),
+        }
+    }
+}
+
+// 実行をまたいで変数を保持する、どちらかのバックエンドの実行環境
+pub enum Runtime {
+    Evaluator(Rc<RefCell<Environment>>),
+    Vm(Box<(Compiler, Vm)>),
+}
+
+impl Runtime {
+    pub fn new(backend: Backend) -> Self {
+        match backend {
+            Backend::Evaluator => Runtime::Evaluator(evaluator::new_environment()),
+            Backend::Vm => Runtime::Vm(Box::new((Compiler::new(), Vm::new()))),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Rc<Object>) {
+        match self {
+            Runtime::Evaluator(env) => {
+                env.borrow_mut().set(name, value);
+            }
+            Runtime::Vm(state) => {
+                let (compiler, vm) = &mut **state;
+                let index = compiler.define_global(name);
+                vm.set_global(index, value);
+            }
+        }
+    }
+
+    pub fn run(&mut self, program: ast::Program) -> Result<Rc<Object>, EvalError> {
+        match self {
+            Runtime::Evaluator(env) => evaluator::eval(Node::Program(program), env),
+            Runtime::Vm(state) => {
+                let (compiler, vm) = &mut **state;
+                let main = compiler.compile(&program);
+                let global_names = compiler.global_names();
+                vm.run(&Bytecode {
+                    main,
+                    constants: compiler.constants(),
+                    global_names: &global_names,
+                })
+            }
+        }
+    }
+}
+
+// 定義済みの変数を`{name: value, ...}`の形式で名前順に出力する
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Runtime::Evaluator(env) => write!(f, "{}", env.borrow()),
+            Runtime::Vm(state) => {
+                let (compiler, vm) = &**state;
+                let mut entries: Vec<_> = compiler
+                    .global_names()
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, name)| Some((name, vm.global(i)?)))
+                    .collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
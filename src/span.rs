@@ -0,0 +1,59 @@
+use core::fmt;
+
+// 読み込んだソースの番号。Files::addで割り当て、0は名前のない入力(REPLで入力した行など)を表す
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub u32);
+
+// FileIdからファイル名を引くための表
+#[derive(Debug, Default)]
+pub struct Files {
+    names: Vec<String>,
+}
+
+impl Files {
+    pub fn add(&mut self, name: &str) -> FileId {
+        self.names.push(name.to_string());
+        FileId(self.names.len() as u32)
+    }
+
+    pub fn name(&self, file: FileId) -> Option<&str> {
+        let index = file.0.checked_sub(1)?;
+        self.names.get(index as usize).map(String::as_str)
+    }
+}
+
+// ソース上の位置。line/columnは1始まりで、columnは文字単位で数える。
+// start/endはバイトオフセットの半開区間。
+// エラー値に埋め込まれるので、大きくならないようu32で持つ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub line: u32,
+    pub column: u32,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    // selfの先頭からotherの末尾までを覆うSpanを返す
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+
+    // ファイル名が分かれば`name:line:column`、分からなければ`line:column`の形にする
+    pub fn display(self, files: &Files) -> String {
+        match files.name(self.file) {
+            Some(name) => format!("{}:{}", name, self),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -1,15 +1,31 @@
+use crate::span::Span;
 use core::fmt;
+use num_bigint::BigInt;
 use std::rc::Rc;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     Illegal(Rc<String>),
+    // 閉じていない文字列や不正なエスケープなど、字句解析のエラー。メッセージを持つ
+    Error(Rc<String>),
     EOF,
+    // `//`と`/* */`のコメント。区切りも含めたソースのままの文字列を持つ。
+    // Lexer::with_triviaのときだけ出てくる
+    Comment(Rc<String>),
 
     // Identifiers + literals
     Ident(Rc<String>),
     Int(i64),
+    // i64に収まらない整数リテラル
+    BigInt(BigInt),
+    Float(f64),
     String(Rc<String>),
+    // `"a${x}b${y}c"`は、TemplateHead("a")、x、TemplateMiddle("b")、y、
+    // TemplateTail("c")の順に分けて返す
+    TemplateHead(Rc<String>),
+    TemplateMiddle(Rc<String>),
+    TemplateTail(Rc<String>),
 
     // Operators
     Assign,
@@ -18,13 +34,40 @@ pub enum Token {
     Bang,
     Asterisk,
     Slash,
+    Percent,
+    Power,
 
     Lt,
     Gt,
+    LtEq,
+    GtEq,
 
     Eq,
     NotEq,
 
+    And,
+    Or,
+
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+
+    // `+=`などの複合代入。`x op= e`は`x = x op e`と同じ
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    PowerAssign,
+    AmpersandAssign,
+    PipeAssign,
+    CaretAssign,
+    ShiftLeftAssign,
+    ShiftRightAssign,
+
     // Delimiters
     Comma,
     Semicolon,
@@ -45,6 +88,17 @@ pub enum Token {
     If,
     Else,
     Return,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 impl Token {
@@ -57,6 +111,11 @@ impl Token {
             "if" => Token::If,
             "else" => Token::Else,
             "return" => Token::Return,
+            "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
             _ => Token::Ident(Rc::new(ident)),
         }
     }
@@ -67,19 +126,51 @@ impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Token::Illegal(s) => write!(f, "Illegal({})", s),
+            Token::Error(message) => write!(f, "{}", message),
+            Token::Comment(s) => write!(f, "{}", s),
             Token::Ident(s) => write!(f, "Ident({})", s),
             Token::Int(i) => write!(f, "Int({})", i),
+            Token::BigInt(i) => write!(f, "Int({})", i),
+            Token::Float(x) => write!(f, "Float({:?})", x),
             Token::String(s) => write!(f, "String({})", s),
+            // テンプレートの区切りはエラーメッセージで分かるように、埋め込みの終わりの`}`として示す
+            Token::TemplateHead(_) => write!(f, "template string"),
+            Token::TemplateMiddle(_) | Token::TemplateTail(_) => {
+                write!(f, "`}}` of template string")
+            }
             Token::Assign => write!(f, "="),
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
             Token::Bang => write!(f, "!"),
             Token::Asterisk => write!(f, "*"),
             Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Power => write!(f, "**"),
             Token::Lt => write!(f, "<"),
             Token::Gt => write!(f, ">"),
+            Token::LtEq => write!(f, "<="),
+            Token::GtEq => write!(f, ">="),
             Token::Eq => write!(f, "=="),
             Token::NotEq => write!(f, "!="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Ampersand => write!(f, "&"),
+            Token::Pipe => write!(f, "|"),
+            Token::Caret => write!(f, "^"),
+            Token::Tilde => write!(f, "~"),
+            Token::ShiftLeft => write!(f, "<<"),
+            Token::ShiftRight => write!(f, ">>"),
+            Token::PlusAssign => write!(f, "+="),
+            Token::MinusAssign => write!(f, "-="),
+            Token::AsteriskAssign => write!(f, "*="),
+            Token::SlashAssign => write!(f, "/="),
+            Token::PercentAssign => write!(f, "%="),
+            Token::PowerAssign => write!(f, "**="),
+            Token::AmpersandAssign => write!(f, "&="),
+            Token::PipeAssign => write!(f, "|="),
+            Token::CaretAssign => write!(f, "^="),
+            Token::ShiftLeftAssign => write!(f, "<<="),
+            Token::ShiftRightAssign => write!(f, ">>="),
             Token::Comma => write!(f, ","),
             Token::Semicolon => write!(f, ";"),
             Token::Colon => write!(f, ":"),
@@ -96,6 +187,11 @@ impl fmt::Display for Token {
             Token::If => write!(f, "if"),
             Token::Else => write!(f, "else"),
             Token::Return => write!(f, "return"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::In => write!(f, "in"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
             Token::EOF => Ok(()),
         }
     }
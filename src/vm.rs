@@ -0,0 +1,429 @@
+use crate::ast::{Identifier, InfixOperator, PrefixOperator};
+use crate::code::{read_operand, CompiledFunction, Opcode};
+use crate::evaluator;
+use crate::object::{builtins, error::Frame, Closure, EvalError, EvalErrorKind, Object};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+mod test;
+
+pub struct Bytecode<'a> {
+    pub main: Rc<CompiledFunction>,
+    pub constants: &'a [Rc<Object>],
+    // グローバル変数の名前。未定義の変数を参照したときのエラーに使う
+    pub global_names: &'a [Identifier],
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    // 次に実行する命令の位置
+    ip: usize,
+    // 実行中の命令の先頭の位置。エラーの位置や呼び出し元を調べるのに使う
+    current: usize,
+    // ローカル変数の先頭のスタック上の位置
+    base_pointer: usize,
+    // 実行中のループに入ったときのスタックの高さ。内側のループが末尾に来る
+    loops: Vec<usize>,
+}
+
+impl CallFrame {
+    fn new(closure: Rc<Closure>, base_pointer: usize) -> Self {
+        CallFrame {
+            closure,
+            ip: 0,
+            current: 0,
+            base_pointer,
+            loops: Vec::new(),
+        }
+    }
+
+    fn function(&self) -> &CompiledFunction {
+        &self.closure.function
+    }
+
+    fn read_operand(&mut self, width: usize) -> usize {
+        let operand = read_operand(&self.function().instructions.0, self.ip, width);
+        self.ip += width;
+        operand
+    }
+}
+
+// グローバル変数は実行をまたいで保持するので、REPLでは1つのVmを使い続ける
+pub struct Vm {
+    globals: Vec<Option<Rc<Object>>>,
+    stack: Vec<Rc<Object>>,
+    frames: Vec<CallFrame>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm {
+            globals: Vec::new(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn global(&self, index: usize) -> Option<Rc<Object>> {
+        self.globals.get(index).cloned().flatten()
+    }
+
+    pub fn set_global(&mut self, index: usize, value: Rc<Object>) {
+        if self.globals.len() <= index {
+            self.globals.resize(index + 1, None);
+        }
+        self.globals[index] = Some(value);
+    }
+
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<Rc<Object>, EvalError> {
+        if self.globals.len() < bytecode.global_names.len() {
+            self.globals.resize(bytecode.global_names.len(), None);
+        }
+        self.stack.clear();
+        self.frames.clear();
+        let main = Rc::new(Closure {
+            function: Rc::clone(&bytecode.main),
+            free: Vec::new(),
+        });
+        let mut frame = CallFrame::new(main, 0);
+        loop {
+            match self.step(&mut frame, bytecode) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => (),
+                Err(error) => return Err(self.locate(error, &frame)),
+            }
+        }
+    }
+
+    // 命令を1つ実行する。メインの関数から戻ったらその値を返す
+    fn step(
+        &mut self,
+        frame: &mut CallFrame,
+        bytecode: &Bytecode,
+    ) -> Result<Option<Rc<Object>>, EvalError> {
+        frame.current = frame.ip;
+        let op = Opcode::from_byte(frame.function().instructions.0[frame.ip]);
+        frame.ip += 1;
+        match op {
+            Opcode::Constant => {
+                let index = frame.read_operand(4);
+                self.push(Rc::clone(&bytecode.constants[index]));
+            }
+            Opcode::Pop => {
+                self.pop();
+            }
+            Opcode::Null => self.push(Rc::new(Object::Null)),
+            Opcode::True => self.push(Rc::new(Object::Boolean(true))),
+            Opcode::False => self.push(Rc::new(Object::Boolean(false))),
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Mod
+            | Opcode::Pow
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::LessThan
+            | Opcode::GreaterThan
+            | Opcode::LessEqual
+            | Opcode::GreaterEqual
+            | Opcode::BitAnd
+            | Opcode::BitOr
+            | Opcode::BitXor
+            | Opcode::ShiftLeft
+            | Opcode::ShiftRight => {
+                let operator = match op {
+                    Opcode::Add => InfixOperator::Plus,
+                    Opcode::Sub => InfixOperator::Minus,
+                    Opcode::Mul => InfixOperator::Asterisk,
+                    Opcode::Div => InfixOperator::Slash,
+                    Opcode::Mod => InfixOperator::Percent,
+                    Opcode::Pow => InfixOperator::Power,
+                    Opcode::Equal => InfixOperator::Eq,
+                    Opcode::NotEqual => InfixOperator::NotEq,
+                    Opcode::LessThan => InfixOperator::Lt,
+                    Opcode::GreaterThan => InfixOperator::Gt,
+                    Opcode::LessEqual => InfixOperator::LtEq,
+                    Opcode::GreaterEqual => InfixOperator::GtEq,
+                    Opcode::BitAnd => InfixOperator::BitAnd,
+                    Opcode::BitOr => InfixOperator::BitOr,
+                    Opcode::BitXor => InfixOperator::BitXor,
+                    Opcode::ShiftLeft => InfixOperator::ShiftLeft,
+                    _ => InfixOperator::ShiftRight,
+                };
+                let right = self.pop();
+                let left = self.pop();
+                let result = evaluator::eval_infix_expression(operator, left, right)?;
+                self.push(result);
+            }
+            Opcode::Minus | Opcode::Bang | Opcode::BitNot => {
+                let operator = match op {
+                    Opcode::Minus => PrefixOperator::Minus,
+                    Opcode::Bang => PrefixOperator::Bang,
+                    _ => PrefixOperator::BitNot,
+                };
+                let right = self.pop();
+                let result = evaluator::eval_prefix_expression(operator, right)?;
+                self.push(result);
+            }
+            Opcode::Jump => {
+                frame.ip = frame.read_operand(4);
+            }
+            Opcode::JumpNotTruthy => {
+                let target = frame.read_operand(4);
+                if !evaluator::is_truthy(&self.pop()) {
+                    frame.ip = target;
+                }
+            }
+            // 変数の値があれば残したまま飛ぶ。letをまだ実行していなければ取り除き、
+            // 続く外側のスコープの変数を読み書きする命令に進む
+            Opcode::JumpIfDefined => {
+                let target = frame.read_operand(4);
+                if matches!(**self.stack.last().unwrap(), Object::Unset) {
+                    self.pop();
+                } else {
+                    frame.ip = target;
+                }
+            }
+            Opcode::EnterLoop => frame.loops.push(self.stack.len()),
+            Opcode::ExitLoop => {
+                frame.loops.pop();
+            }
+            // breakとcontinueで、本体の途中の値をループに入ったときの高さまで捨てる
+            Opcode::Unwind => {
+                let height = *frame.loops.last().unwrap();
+                self.stack.truncate(height);
+            }
+            // 繰り返す値の並びと、次に取り出す位置を積む
+            Opcode::Iter => {
+                let iterable = self.pop();
+                self.push(evaluator::iteration_sequence(iterable)?);
+                self.push(Rc::new(Object::Integer(0)));
+            }
+            // 次の値があれば位置を進めて値を積み、なければ飛ぶ
+            Opcode::IterNext => {
+                let target = frame.read_operand(4);
+                let position = self.stack.len() - 1;
+                let i = match &*self.stack[position] {
+                    Object::Integer(i) => *i as usize,
+                    other => panic!("not an iteration index: {:?}", other),
+                };
+                match evaluator::iteration_item(&self.stack[position - 1], i) {
+                    Some(item) => {
+                        self.stack[position] = Rc::new(Object::Integer(i as i64 + 1));
+                        self.push(item);
+                    }
+                    None => frame.ip = target,
+                }
+            }
+            Opcode::GetGlobal => {
+                let index = frame.read_operand(4);
+                match self.global(index) {
+                    Some(value) => self.push(value),
+                    None => {
+                        let name = bytecode.global_names[index].clone();
+                        return Err(EvalErrorKind::UnknownIdentifier(name).into());
+                    }
+                }
+            }
+            Opcode::SetGlobal => {
+                let index = frame.read_operand(4);
+                let value = self.pop();
+                self.set_global(index, value);
+            }
+            // letで定義済みのグローバル変数にだけ代入できる
+            Opcode::AssignGlobal => {
+                let index = frame.read_operand(4);
+                let value = self.pop();
+                if self.global(index).is_none() {
+                    let name = bytecode.global_names[index].clone();
+                    return Err(EvalErrorKind::UndefinedAssignment(name).into());
+                }
+                self.set_global(index, value);
+            }
+            Opcode::GetLocal => {
+                let index = frame.read_operand(2);
+                self.push(Rc::clone(&self.stack[frame.base_pointer + index]));
+            }
+            Opcode::SetLocal => {
+                let index = frame.read_operand(2);
+                self.stack[frame.base_pointer + index] = self.pop();
+            }
+            Opcode::GetFree => {
+                let index = frame.read_operand(2);
+                self.push(Rc::clone(&frame.closure.free[index]));
+            }
+            // ループで定義し直すときは、クロージャと共有しているセルをそのまま使う
+            Opcode::DefineCell => {
+                let index = frame.read_operand(2);
+                let value = self.pop();
+                let slot = &mut self.stack[frame.base_pointer + index];
+                match &**slot {
+                    Object::Cell(cell) => *cell.borrow_mut() = value,
+                    _ => *slot = Rc::new(Object::Cell(RefCell::new(value))),
+                }
+            }
+            Opcode::GetCell => match &*self.pop() {
+                Object::Cell(cell) => self.push(Rc::clone(&cell.borrow())),
+                other => panic!("not a cell: {:?}", other),
+            },
+            Opcode::SetCell => {
+                let cell = self.pop();
+                let value = self.pop();
+                match &*cell {
+                    Object::Cell(cell) => *cell.borrow_mut() = value,
+                    other => panic!("not a cell: {:?}", other),
+                }
+            }
+            Opcode::CannotAssign => {
+                let index = frame.read_operand(4);
+                let name = match &*bytecode.constants[index] {
+                    Object::String(name) => Rc::clone(name),
+                    other => panic!("not a name constant: {:?}", other),
+                };
+                return Err(EvalErrorKind::UndefinedAssignment(name).into());
+            }
+            Opcode::GetBuiltin => {
+                let index = frame.read_operand(1);
+                self.push(Rc::new(Object::Builtin(builtins::BUILTINS[index])));
+            }
+            Opcode::Array => {
+                let length = frame.read_operand(4);
+                let elements = self.stack.split_off(self.stack.len() - length);
+                self.push(Rc::new(Object::Array(elements)));
+            }
+            Opcode::Hash => {
+                let length = frame.read_operand(4);
+                let elements = self.stack.split_off(self.stack.len() - length * 2);
+                let hash = build_hash(elements).map_err(|(i, error)| {
+                    match frame.function().spans_at(frame.current).nth(i) {
+                        Some(span) => error.at(span),
+                        None => error,
+                    }
+                })?;
+                self.push(Rc::new(hash));
+            }
+            // 値をObjectのDisplayで文字列にしてつなげる
+            Opcode::Concat => {
+                let length = frame.read_operand(4);
+                let parts = self.stack.split_off(self.stack.len() - length);
+                let result: String = parts.iter().map(|part| part.to_string()).collect();
+                self.push(Rc::new(Object::String(Rc::new(result))));
+            }
+            Opcode::Index => {
+                let index = self.pop();
+                let left = self.pop();
+                let result = evaluator::eval_index_expression(left, index)?;
+                self.push(result);
+            }
+            Opcode::Call => {
+                let num_arguments = frame.read_operand(2);
+                self.call(frame, num_arguments)?;
+            }
+            Opcode::ReturnValue => {
+                let value = self.pop();
+                match self.frames.pop() {
+                    Some(caller) => {
+                        // 引数とローカル変数に加えて、呼び出した関数自身も取り除く
+                        self.stack.truncate(frame.base_pointer - 1);
+                        *frame = caller;
+                        self.push(value);
+                    }
+                    None => return Ok(Some(value)),
+                }
+            }
+            Opcode::Closure => {
+                let index = frame.read_operand(4);
+                let num_free = frame.read_operand(2);
+                let function = match &*bytecode.constants[index] {
+                    Object::CompiledFunction(function) => Rc::clone(function),
+                    other => panic!("not a function constant: {:?}", other),
+                };
+                let free = self.stack.split_off(self.stack.len() - num_free);
+                self.push(Rc::new(Object::Closure(Rc::new(Closure {
+                    function,
+                    free,
+                }))));
+            }
+        }
+        Ok(None)
+    }
+
+    fn call(&mut self, frame: &mut CallFrame, num_arguments: usize) -> Result<(), EvalError> {
+        let base_pointer = self.stack.len() - num_arguments;
+        let callee = Rc::clone(&self.stack[base_pointer - 1]);
+        match &*callee {
+            Object::Closure(closure) => {
+                let function = &closure.function;
+                if function.num_parameters != num_arguments {
+                    return Err(EvalErrorKind::ArityMismatch {
+                        expected: function.num_parameters,
+                        got: num_arguments,
+                    }
+                    .into());
+                }
+                if self.frames.len() >= evaluator::MAX_CALL_DEPTH {
+                    return Err(EvalErrorKind::StackOverflow.into());
+                }
+                for _ in num_arguments..function.num_locals {
+                    self.push(Rc::new(Object::Unset));
+                }
+                let callee = CallFrame::new(Rc::clone(closure), base_pointer);
+                self.frames.push(mem::replace(frame, callee));
+            }
+            Object::Builtin(builtin) => {
+                let arguments = self.stack.split_off(base_pointer);
+                self.pop();
+                let result = (builtin.func)(arguments)?;
+                self.push(result);
+            }
+            other => return Err(EvalErrorKind::NotAFunction(other.type_name()).into()),
+        }
+        Ok(())
+    }
+
+    // エラーに実行中の命令の位置と、評価器と同じ形の呼び出し履歴を付ける
+    fn locate(&self, error: EvalError, frame: &CallFrame) -> EvalError {
+        let mut error = match frame.function().span_at(frame.current) {
+            Some(span) => error.at(span),
+            None => error,
+        };
+        let mut callee = frame;
+        for caller in self.frames.iter().rev() {
+            if let Some(call_site) = caller.function().span_at(caller.current) {
+                error.trace.push(Frame {
+                    function: callee.function().name.clone(),
+                    call_site,
+                });
+            }
+            callee = caller;
+        }
+        error
+    }
+
+    fn push(&mut self, object: Rc<Object>) {
+        self.stack.push(object);
+    }
+
+    fn pop(&mut self) -> Rc<Object> {
+        self.stack.pop().expect("stack underflow")
+    }
+}
+
+// キーと値が交互に並んだ要素からハッシュを作る。
+// 使えないキーがあれば、何番目のキーかとエラーを返す
+#[allow(clippy::mutable_key_type)]
+fn build_hash(elements: Vec<Rc<Object>>) -> Result<Object, (usize, EvalError)> {
+    let mut hash = HashMap::new();
+    let mut elements = elements.into_iter();
+    let mut i = 0;
+    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+        if !key.is_hashable() {
+            return Err((i, EvalErrorKind::UnusableAsHashKey(key.type_name()).into()));
+        }
+        hash.insert(key, value);
+        i += 1;
+    }
+    Ok(Object::Hash(hash))
+}
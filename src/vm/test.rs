@@ -0,0 +1,315 @@
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::runtime::{Backend, Runtime};
+    use crate::span::Files;
+
+    fn run(backend: Backend, input: &str) -> Result<String, String> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let (program, errors) = parser.program();
+        assert!(errors.is_empty(), "parse errors: {:?}", errors);
+        Runtime::new(backend)
+            .run(program)
+            .map(|o| o.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    // 評価器とVMで、値もエラーもまったく同じになることを確かめる
+    const CONFORMANCE: &[&str] = &[
+        "5",
+        "-5 + 10 * 2 - 3 / 3",
+        "(1 + 2) * 3 == 9",
+        "1 < 2 != 2 > 1",
+        "!true == !!false",
+        "!5",
+        "1.5 * 2 - 0.25",
+        "-1e-3",
+        "7 / 2 + 7 / 2.0",
+        "3 < 3.5 == (3.5 > 3)",
+        "1 == 1.0",
+        "int(-2.5) + float(2) + float(\"1.5\")",
+        "str(0.1 + 0.2)",
+        "int(\"x\")",
+        "{2.5: 1}",
+        "1 / 0",
+        "1.0 / 0",
+        "9223372036854775807 + 1",
+        "let f = fn(x) { 10 / x }; f(0)",
+        "-(-9223372036854775807 - 1)",
+        "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(30)",
+        "99999999999999999999 - 99999999999999999998 == 1",
+        "{18446744073709551616: 1}[18446744073709551616] + int(1e19)",
+        "18446744073709551616 / 0",
+        "0xff + 0o17 * 0b10 - 1_000",
+        "[1 <= 2, 2 >= 3, 7 % -3, 7.5 % 2, 2 ** 10, 2 ** -2, 3 ** 50, -2 ** 2]",
+        "1 % 0",
+        "2 ** 99999999999",
+        "{} >= 1",
+        "let n = puts(); [true && 1, 0 && false, false || n, n || 2]",
+        "false && undefined",
+        "true || undefined()",
+        "true && undefined",
+        "let f = fn(n) { n > 0 && f(n - 1) || n == 0 }; f(5)",
+        "[6 & 3, 6 | 3, 6 ^ 3, ~6, 1 << 62, -9 >> 1, (1 << 63) >> 62, ~(1 << 62 << 40)]",
+        "let flags = 0; let flags = flags | 1 << 3; flags & (1 << 3) != 0",
+        "1 << 64",
+        "1 >> -3",
+        "1 << 62 << 1048600",
+        "7 ** 4000000000",
+        "~\"a\"",
+        "1.5 ^ 2",
+        "0xffff_ffff_ffff_ffff + 1",
+        "!null_value_is_not_defined",
+        r#""foo" + "bar""#,
+        r#"let n = "Ann"; "Hi ${n}, ${1 + 2} ${[1.5, "x"]} ${ {"k": "${n}!"}["k"] }""#,
+        r#"let f = fn(x) { "<${x}>" }; f(f(1))"#,
+        r#""${1 / 0}""#,
+        r#""a" == "a""#,
+        "if (1 > 2) { 10 }",
+        "if (1) { 10 } else { 20 }",
+        "if (false) { 10 } else { if (true) { 30 } }",
+        "if (true) { }",
+        "let a = 5; let b = a * 2; a + b",
+        "let a = 1;",
+        "let a = 1; let a = a + 1; a",
+        "[1, 2 * 2, 3 + 3]",
+        "[1, 2, 3][1 + 1]",
+        "[1, 2, 3][3]",
+        "[1, 2, 3][-1]",
+        r#"{"one": 1, 2: "two", true: [3]}[true]"#,
+        r#"{"a": 1, "a": 2}["a"]"#,
+        "{}[1]",
+        "return 10; 9;",
+        "9; return 2 * 5; 9;",
+        "if (10 > 1) { if (10 > 1) { return 10; } return 1; }",
+        "let f = fn(x) { if (x > 1) { return 1; } return 2; }; f(5)",
+        "let f = fn() { if (true) { if (true) { return 1; } } 2 }; f() + 10",
+        "let inner = fn() { return 1; }; let outer = fn() { inner(); 2 }; outer()",
+        "let f = fn() { }; f()",
+        "let f = fn() { let a = 1; }; f()",
+        "fn(a, b) { let c = a + b; c * 2 }(1, 2)",
+        "let g = 50; let f = fn(a) { let b = a * 2; g - b }; f(10) + f(20)",
+        "let adder = fn(x) { fn(y) { x + y } }; let add_two = adder(2); add_two(3)",
+        "let f = fn(a) { fn(b) { fn(c) { a + b + c } } }; f(1)(2)(3)",
+        "let f = fn(a) { let b = a; fn() { let c = b; fn() { a + c } } }; f(4)()()",
+        "let get = fn() { later }; let later = 10; get()",
+        "let x = 1; let f = fn() { x }; let x = 2; f()",
+        "fn() { let a = 1; let f = fn() { a }; let a = 2; f() }()",
+        "fn(a) { let f = fn() { a }; let a = a + 1; f() }(1)",
+        "let x = 1; fn() { let a = x; let x = 2; [a, x] }()",
+        "fn() { let get = fn() { later }; let later = 10; get() }()",
+        "let f = fn() { let g = fn() { h() }; let h = fn() { 5 }; g() }; f()",
+        "fn() { let g = fn() { fn() { h * 2 } }; let k = g(); let h = 21; k() }()",
+        "fn() { let a = b; let b = 1; a }()",
+        // letの前に呼んだクロージャは、外側のスコープの名前を使う
+        "let f = fn() { let g = fn() { y }; let r = g(); let y = 1; [r] }; f()",
+        "let y = 5; let f = fn() { let g = fn() { y }; let r = g(); let y = 1; [r, g()] }; f()",
+        "fn() { let g = fn() { h }; if (false) { let h = 1 }; g() }()",
+        "fn() { if (false) { let h = 1 }; h }()",
+        "let h = 7; fn() { if (false) { let h = 1 }; h }()",
+        "let y = 8; fn() { let get = fn() { fn() { y } }; let r = get()(); let y = 1; [r, get()()] }()",
+        "fn() { let y = 2; fn() { let g = fn() { y }; let a = g(); let y = 3; [a, g()] }() }()",
+        "let y = 5; let f = fn() { let set = fn() { y = 9 }; set(); let y = 1; y }; [f(), y]",
+        "fn() { let set = fn() { z = 1 }; set(); let z = 2 }()",
+        "let n = 10; let r = fn() { let inc = fn() { n += 1 }; let a = inc(); let n = 0; [a, inc()] }(); [r, n]",
+        "fn() { let r = []; for (i in range(2)) { if (i == 1) { r = push(r, x) }; let x = i } r }()",
+        "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+        "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+         let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+         even(10)",
+        "let wrap = fn() {
+           let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };
+           count(5)
+         };
+         wrap()",
+        "let map = fn(arr, f) {
+           let iter = fn(arr, acc) {
+             if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) }
+           };
+           iter(arr, [])
+         };
+         map([1, 2, 3], fn(x) { x * x })",
+        r#"len("hello") + len([1, 2])"#,
+        "last(push(rest([1, 2, 3]), 4))",
+        "let len = fn(x) { 42 }; len([])",
+        "puts(1, 2)",
+        "let f = fn() { 1 }; f",
+        "len",
+        "5 + true;",
+        "let a = 1;\nlet b = a + foo;",
+        "let f = fn() {\n  -true\n};\nf()",
+        "[1, 2][0](1)",
+        "true + false",
+        r#""a" - 1"#,
+        r#"-"a""#,
+        "true()",
+        "1[0]",
+        "{[1]: 2}",
+        r#"{"a": 1, fn() { 1 }: 2}"#,
+        "{1: 2}[fn(x) { x }]",
+        "fn(x) { x }()",
+        "len(1)",
+        r#"len("one", "two")"#,
+        "fn() { first(1) }()",
+        "let f = fn() { f }; f() == f()",
+        "let inner = fn(x) { x + true };
+let outer = fn(x) { inner(x) };
+let run = fn() { fn() { outer(1) }() };
+run()",
+        "let down = fn(n) { if (n == 0) { n + true } else { down(n - 1) } }; down(3)",
+        "let f = fn() { f() }; f()",
+        "let deep = fn(n) { if (n == 0) { 0 } else { 1 + deep(n - 1) } }; deep(4000)",
+        "let x = 1; x = x + 1; x += 10; let y = x = x * 2; [x, y]",
+        "let a = 5; a <<= 2; a %= 7; a **= 2; a |= 64; a ^= 1; a &= 127; a >>= 1; a -= 1; a /= 2; a",
+        r#"let s = "a"; s += "b"; s"#,
+        "let x = 1; let f = fn() { x = 2 }; f(); x",
+        "let counter = fn() { let n = 0; fn() { n += 1 } }; let c = counter(); c(); c(); [c(), counter()()]",
+        "let f = fn(a) { a *= 2; let g = fn() { a = a + 1 }; g(); a }; f(5)",
+        "fn() { let n = 0; let add = fn(k) { fn() { n += k } }; add(2)(); add(3)(); n }()",
+        "fn() { let x = 1; let f = fn() { x }; x = 2; f() }()",
+        "let t = 0; let f = fn(n) { if (n > 0) { t += n; f(n - 1) } }; f(4); t",
+        "fn() { let g = fn() { g = 1 }; g(); g }()",
+        "fn() { let g = fn(n) { if (n > 0) { g(n - 1) } else { 7 } }; let h = g; g = fn(n) { 100 }; h(3) }()",
+        "y = 1",
+        "len = 1",
+        "z += 1",
+        "fn() { w = 1 }()",
+        r#"let x = 1; x -= "a""#,
+        "let i = 0; let s = 0; while (i < 5) { i += 1; s += i }; s",
+        "while (false) { 1 }",
+        "let i = 0; while (true) { i += 1; if (i == 3) { break } }; i",
+        "let s = 0; for (x in [1, 2, 3]) { s += x }; s",
+        r#"let s = 0; for (k in {1: "a", 2: "b"}) { s += k }; s"#,
+        r#"let r = []; for (k in {"b": 1, 10: 2, "a": 3, true: 4, 1 << 70: 5, -3: 6, false: 7}) { r = push(r, k) }; r"#,
+        r#"let s = ""; for (c in "héllo") { s = c + s }; s"#,
+        "let s = 0; for (i in range(1, 5)) { s += i }; s",
+        "let s = 0; for (i in range(100000)) { s += i }; s",
+        "[range(3), len(range(2, 7)), len(range(5, 1)), range(-2, 0)]",
+        "let s = 0; for (i in range(10)) { if (i == 6) { break } if (i % 2 == 0) { continue } s += i }; s",
+        "let r = []; for (i in range(3)) { for (j in range(3)) { if (j > i) { break } r = push(r, j) } }; r",
+        "let s = 0; for (i in range(5)) { s += [1, if (i > 2) { break } else { i }][1] }; s",
+        "let n = 0; for (x in [1, 2]) { for (y in \"ab\") { n += 1; continue; n = 100 } }; n",
+        "let f = fn(n) { for (i in range(n)) { if (i * i > n) { return i } } }; f(10)",
+        "let f = fn(a) { let s = 0; while (a > 0) { let d = a % 10; s += d; a /= 10 }; s }; f(1234)",
+        "let fs = []; for (i in range(3)) { fs = push(fs, fn() { i }) }; [fs[0](), fs[2]()]",
+        "fn() { let fs = []; for (i in range(3)) { let j = i * 10; fs = push(fs, fn() { i + j }) }; [fs[0](), fs[2]()] }()",
+        "fn() { for (x in [1]) { let g = fn() { x }; x = 5; return g() } }()",
+        "for (x in 5) { x }",
+        "fn() { for (x in [1, 2]) { x + true } }()",
+        "range(1.5)",
+        "range(1, 2, 3)",
+        "range(99999999999999999999)",
+    ];
+
+    #[test]
+    fn test_backends_conform() {
+        for input in CONFORMANCE {
+            assert_eq!(
+                run(Backend::Vm, input),
+                run(Backend::Evaluator, input),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_display_is_sorted() {
+        // 同じ結果になるだけでなく、どちらもキーの順に並ぶことを確かめる
+        let input = r#"{"b": 2, true: 0, 10: 1, "a": [1], -3: {2: 0, 1: 0}, false: 1, 18446744073709551616: 5}"#;
+        let expected =
+            "{false: 1,true: 0,-3: {1: 0,2: 0},10: 1,18446744073709551616: 5,a: [1],b: 2}";
+        for backend in [Backend::Evaluator, Backend::Vm] {
+            for _ in 0..10 {
+                assert_eq!(run(backend, input), Ok(expected.to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_vm_results() {
+        assert_eq!(
+            run(Backend::Vm, "let f = fn(a) { fn(b) { a * b } }; f(6)(7)"),
+            Ok("42".to_string())
+        );
+        assert_eq!(
+            run(
+                Backend::Vm,
+                "let down = fn(n) { if (n == 0) { n + true } else { down(n - 1) } }; down(3)"
+            ),
+            Err("1:34: type mismatch: INTEGER + BOOLEAN
+    in down called at 1:52
+    ... repeated 2 more times
+    in down called at 1:69"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn test_vm_assignment_keeps_globals() {
+        let mut runtime = Runtime::new(Backend::Vm);
+        for (input, expected) in [
+            ("let n = 1; let inc = fn() { n += 1 };", "null"),
+            ("inc(); inc()", "3"),
+            ("n = 10; inc()", "11"),
+        ] {
+            let (program, _) = Parser::new(Lexer::new(input)).program();
+            assert_eq!(runtime.run(program).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_vm_stack_overflow() {
+        let error = run(Backend::Vm, "let f = fn() { f() }; f()").unwrap_err();
+        assert!(
+            error.starts_with("1:16: stack overflow\n"),
+            "error: {}",
+            error
+        );
+    }
+
+    // 別のファイルで定義した関数のエラーは、それぞれの位置にファイル名を付けて表示する
+    #[test]
+    fn test_errors_name_their_files() {
+        for backend in [Backend::Evaluator, Backend::Vm] {
+            let mut files = Files::default();
+            let mut runtime = Runtime::new(backend);
+            let mut error = None;
+            for (name, input) in [
+                ("lib.mk", "let f = fn(x) { x + true };"),
+                ("main.mk", "\nf(1)"),
+            ] {
+                let file = files.add(name);
+                let (program, _) = Parser::new(Lexer::new(input).with_file(file)).program();
+                error = runtime.run(program).err();
+            }
+            assert_eq!(
+                error.map(|e| e.render(&files)),
+                Some(
+                    "lib.mk:1:17: type mismatch: INTEGER + BOOLEAN\n    in f called at main.mk:2:1"
+                        .to_string()
+                ),
+                "backend: {:?}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_vm_keeps_globals_between_runs() {
+        let mut runtime = Runtime::new(Backend::Vm);
+        for (input, expected) in [
+            ("let a = 1; let f = fn(x) { x + a };", "null"),
+            ("let a = 10; f(1)", "11"),
+            ("let g = fn() { b }; let b = 5; g()", "5"),
+        ] {
+            let (program, _) = Parser::new(Lexer::new(input)).program();
+            assert_eq!(runtime.run(program).unwrap().to_string(), expected);
+        }
+        assert_eq!(
+            runtime.to_string(),
+            "{a: 10, b: 5, f: Function, g: Function}"
+        );
+    }
+}